@@ -0,0 +1,80 @@
+// Counts the heap allocations of a query. The queries walk down the trie with
+// an offset into the bits they look for, so rank allocates nothing at all;
+// select and select_all only keep the way down, or collect their result.
+//
+// cargo bench --bench rank_allocations [-- number of queries]
+
+extern crate dyn_bit_vec;
+extern crate wavelet_trie;
+
+use dyn_bit_vec::DBVec;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::env;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use wavelet_trie::wavelet_trie::WaveletTrie;
+
+// the system allocator, counting every allocation
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+	unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+		ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+		System.alloc(layout)
+	}
+
+	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+		System.dealloc(ptr, layout)
+	}
+
+	unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+		ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+		System.realloc(ptr, layout, new_size)
+	}
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn main() {
+	let nr_queries = env::args().skip(1)
+		.filter(|arg| !arg.starts_with("--"))
+		.filter_map(|arg| arg.parse().ok())
+		.next()
+		.unwrap_or(10_000u64);
+	let texts: Vec<String> = (0..10_000u64).map(|nr| match nr % 4 {
+		0 => format!("user/{}", nr % 100),
+		1 => format!("file/{}.rs", nr % 1000),
+		_ => "INFO".to_string()
+	}).collect();
+	let wt = WaveletTrie::from_iter_str(&texts);
+	let sequence = DBVec::from_bytes(b"user/4");
+
+	println!("{} queries", nr_queries);
+	let rank_str_exact = per_query(nr_queries, |nr| wt.rank_str_exact("user/40", nr).is_some());
+	let rank_str_prefix = per_query(nr_queries, |nr| wt.rank_str_prefix("file/", nr).is_some());
+	let rank = per_query(nr_queries, |nr| wt.rank(&sequence, nr).is_some());
+	let select_str_exact = per_query(nr_queries, |nr| wt.select_str_exact("INFO", nr + 1).is_some());
+	for &(name, allocations) in &[("rank_str_exact", rank_str_exact), ("rank_str_prefix", rank_str_prefix),
+			("rank", rank), ("select_str_exact", select_str_exact)] {
+		println!("{:<18} {:>8.2} allocations per call", name, allocations);
+	}
+	assert_eq!(rank_str_exact, 0.0);
+	assert_eq!(rank_str_prefix, 0.0);
+	assert_eq!(rank, 0.0);
+}
+
+// the average number of allocations of a query
+fn per_query<F: Fn(u64) -> bool>(nr_queries: u64, query: F) -> f64 {
+	let mut found = 0;
+	let before = ALLOCATIONS.load(Ordering::SeqCst);
+	for nr in 0..nr_queries {
+		if query(nr) {
+			found += 1;
+		}
+	}
+	let allocations = ALLOCATIONS.load(Ordering::SeqCst) - before;
+	assert!(found > 0);
+	allocations as f64 / nr_queries as f64
+}
@@ -0,0 +1,51 @@
+// Compares select_all, which maps all positions one level up in one pass,
+// with a select per position per level, as select_all used to do (and as
+// select_many still does).
+//
+// cargo bench --bench select_all [-- number of strings]
+
+extern crate dyn_bit_vec;
+extern crate wavelet_trie;
+
+use dyn_bit_vec::DBVec;
+use std::env;
+use std::time::{Duration, Instant};
+use wavelet_trie::wavelet_trie::WaveletTrie;
+
+fn main() {
+	let nr_strings = env::args().skip(1)
+		.filter(|arg| !arg.starts_with("--"))
+		.filter_map(|arg| arg.parse().ok())
+		.next()
+		.unwrap_or(100_000u64);
+	let texts: Vec<String> = (0..nr_strings).map(|nr| match nr % 10 {
+		6 | 7 => format!("user/{}", nr % 1000),
+		8 | 9 => format!("file/{}.rs", (nr * 7919) % 10007),
+		_ => "INFO".to_string()
+	}).collect();
+	let wt = WaveletTrie::from_iter_str(&texts);
+
+	println!("{} strings", nr_strings);
+	for query in &["INFO", "user/", "user/7", "file/1"] {
+		let sequence = DBVec::from_bytes(query.as_bytes());
+		let count = wt.count(&sequence);
+		let occurrence_nrs: Vec<u64> = (1..count + 1).collect();
+		let per_element = best_of(|| wt.select_many(&sequence, &occurrence_nrs).len());
+		let one_pass = best_of(|| wt.select_all(&sequence).len());
+		println!("{:<12} {:>8} occurrences   per element {:>10.3} ms   one pass {:>10.3} ms",
+			query, count, millis(per_element), millis(one_pass));
+	}
+}
+
+// the fastest of a few runs
+fn best_of<F: FnMut() -> usize>(mut run: F) -> Duration {
+	(0..5).map(|_| {
+		let start = Instant::now();
+		assert!(run() > 0);
+		start.elapsed()
+	}).min().unwrap()
+}
+
+fn millis(duration: Duration) -> f64 {
+	duration.as_secs() as f64 * 1000.0 + f64::from(duration.subsec_nanos()) / 1_000_000.0
+}
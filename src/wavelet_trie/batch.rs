@@ -0,0 +1,146 @@
+// Appending many sequences at once. Instead of walking the trie for every
+// sequence and inserting one bit in the positions of every node on the way,
+// append_batch takes the batch down the trie as groups: all sequences that
+// reach a node add their bits to its positions in one go, then split into a
+// group per child. A leaf gets one run of 0s; where the batch leaves the
+// trie, the new subtrie is built with the bulk builder.
+//
+// The whole batch is checked before the trie changes, so either all
+// sequences are appended or none.
+
+extern crate dyn_bit_vec;
+
+use self::dyn_bit_vec::DBVec;
+use std::cmp::Ordering;
+use std::mem;
+use wavelet_trie::{WaveletTrie, Node, common_prefix_len};
+use wavelet_trie::positions::Positions;
+use wavelet_trie::builder::{build_nodes, build_subtrie, bit_range};
+
+impl WaveletTrie {
+
+	// appends the sequences in their order, like append does one by one
+	pub fn append_batch(&mut self, sequences: &[DBVec]) -> Result<(), &'static str> {
+		if sequences.is_empty() {
+			return Ok(());
+		}
+		// every sequence must fit in the trie, and in the batch
+		for sequence in sequences {
+			self.find_insert(sequence)?;
+		}
+		let mut order: Vec<usize> = (0..sequences.len()).collect();
+		order.sort_by(|&first, &second| compare_bits(&sequences[first], &sequences[second]));
+		for pair in order.windows(2) {
+			let (first, second) = (&sequences[pair[0]], &sequences[pair[1]]);
+			if first.len() < second.len() && common_prefix_len(first, second, 0) == first.len() {
+				return Err("A sequence in the batch is a prefix of another one, which is not allowed.");
+			}
+		}
+
+		if self.len() == 0 {
+			// the new nodes go into the arena that is there, so room reserved for them is used
+			let (nodes, root) = build_nodes(sequences);
+			self.nodes.clear();
+			self.free.clear();
+			self.root = self.add_subtrie(nodes, root);
+			return Ok(());
+		}
+
+		// the node, the numbers of the sequences that reach it, and where its prefix starts in them
+		let mut work = vec![(self.root, (0..sequences.len()).collect::<Vec<usize>>(), 0)];
+		while let Some((id, group, offset)) = work.pop() {
+			let prefix_len = self.node(id).prefix.len();
+			let common_len = group.iter().map(|&nr| common_prefix_len(&self.node(id).prefix, &sequences[nr], offset)).min().unwrap();
+			if common_len == prefix_len && self.node(id).is_leaf() {
+				// all sequences are equal to the ones in this leaf
+				self.node_mut(id).positions.push_run(false, group.len() as u64);
+				continue;
+			}
+
+			// the bit after the common part decides where every sequence goes
+			let split_at = offset + common_len;
+			let mut bits = DBVec::new();
+			let mut left = Vec::new();
+			let mut right = Vec::new();
+			for &nr in &group {
+				let bit = sequences[nr].get(split_at);
+				bits.push(bit);
+				if bit {
+					right.push(nr);
+				} else {
+					left.push(nr);
+				}
+			}
+			if common_len < prefix_len {
+				// split the node: what it holds now goes to one side, the new sequences on the other side to a new subtrie
+				let (bit_self, original_node) = {
+					let node = self.node_mut(id);
+					let (bit_self, suffix_self) = node.prefix.different_suffix(common_len);
+					let pos_len = node.positions.len();
+					let original_node = Node {
+						prefix: suffix_self,
+						positions: mem::replace(&mut node.positions, Positions::from_elem(pos_len, bit_self)),
+						children: node.children.take()
+					};
+					node.prefix = bit_range(&node.prefix, 0, common_len);
+					(bit_self, original_node)
+				};
+				let original_id = self.add_node(original_node);
+				let mut new_group = mem::take(if bit_self { &mut left } else { &mut right });
+				let (nodes, root) = build_subtrie(sequences, &mut new_group, split_at + 1);
+				let new_id = self.add_subtrie(nodes, root);
+				self.node_mut(id).children = match bit_self {
+					false => Some((original_id, new_id)),
+					true => Some((new_id, original_id))
+				};
+			}
+			self.node_mut(id).positions.append(bits);
+			let (left_child, right_child) = self.node(id).children.unwrap();
+			if !right.is_empty() {
+				work.push((right_child, right, split_at + 1));
+			}
+			if !left.is_empty() {
+				work.push((left_child, left, split_at + 1));
+			}
+		}
+		Ok(())
+	}
+
+	// puts the nodes of a subtrie built apart, children before their parent, in
+	// the arena; returns the index of its root there
+	fn add_subtrie(&mut self, nodes: Vec<Node>, root: u32) -> u32 {
+		let mut new_ids = Vec::with_capacity(nodes.len());
+		for mut node in nodes {
+			node.children = node.children.map(|(left, right)| (new_ids[left as usize], new_ids[right as usize]));
+			new_ids.push(self.add_node(node));
+		}
+		new_ids[root as usize]
+	}
+}
+
+// appends the sequences with append_batch; panics if they cannot be appended, and then appends none
+impl Extend<DBVec> for WaveletTrie {
+	fn extend<I: IntoIterator<Item = DBVec>>(&mut self, sequences: I) {
+		let sequences: Vec<DBVec> = sequences.into_iter().collect();
+		if let Err(err) = self.append_batch(&sequences) {
+			panic!("{}", err);
+		}
+	}
+}
+
+// appends the strings like append_str, with append_batch
+impl<'a> Extend<&'a str> for WaveletTrie {
+	fn extend<I: IntoIterator<Item = &'a str>>(&mut self, texts: I) {
+		self.extend(texts.into_iter().map(WaveletTrie::text_to_bitvec));
+	}
+}
+
+// orders sequences bit by bit, with a prefix before the longer sequences
+fn compare_bits(first: &DBVec, second: &DBVec) -> Ordering {
+	let common_len = common_prefix_len(first, second, 0);
+	if common_len < first.len() && common_len < second.len() {
+		first.get(common_len).cmp(&second.get(common_len))
+	} else {
+		first.len().cmp(&second.len())
+	}
+}
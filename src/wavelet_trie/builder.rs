@@ -0,0 +1,204 @@
+// Bulk construction of a trie from sequences in any order. Instead of cutting
+// the sequences into suffixes for every level, the builder keeps one array of
+// indices into the sequences. A node is a range of that array together with
+// the bit position where its prefix starts. The indices of a node are split
+// over its children in place, stably, on the bit after the prefix, like a
+// radix sort that does one bit per level. The prefixes and positions of the
+// nodes are read from the original sequences; nothing else is copied.
+// Once a node is split, its subtries are independent, so from_sequences_parallel
+// builds the subtries of large nodes in separate threads.
+
+extern crate dyn_bit_vec;
+
+use self::dyn_bit_vec::DBVec;
+use std::cmp;
+use std::panic;
+use std::thread;
+use wavelet_trie::{WaveletTrie, Node, push_node};
+use wavelet_trie::positions::Positions;
+
+// subtries of fewer sequences are not worth a thread of their own
+const PARALLEL_THRESHOLD: usize = 4096;
+
+impl WaveletTrie {
+
+	// builds a trie of the strings, each with a terminator as append_str adds it
+	pub fn from_iter_str<I>(strings: I) -> Self where I: IntoIterator, I::Item: AsRef<str> {
+		let sequences: Vec<DBVec> = strings.into_iter().map(|text| WaveletTrie::text_to_bitvec(text.as_ref())).collect();
+		WaveletTrie::from_sequences(&sequences)
+	}
+
+	// like from_sequences, building large subtries on up to "threads" threads.
+	// The result is exactly the same as that of from_sequences.
+	pub fn from_sequences_parallel(sequences: &[DBVec], threads: usize) -> Self {
+		if sequences.is_empty() {
+			return WaveletTrie::new();
+		}
+		let (nodes, root) = build_nodes_parallel(sequences, threads);
+		WaveletTrie::from_nodes(nodes, root)
+	}
+}
+
+// builds the nodes of the trie of "sequences": for every node first its left subtrie,
+// then its right subtrie, then the node itself. Returns the nodes and the index of
+// the root. Panics if a sequence is a prefix of another one.
+pub(crate) fn build_nodes(sequences: &[DBVec]) -> (Vec<Node>, u32) {
+	let mut order: Vec<usize> = (0..sequences.len()).collect();
+	build_subtrie(sequences, &mut order, 0)
+}
+
+// like build_nodes, with up to "threads" threads. A node with at least
+// PARALLEL_THRESHOLD sequences builds its left subtrie in a new thread; the nodes
+// of both subtries are then put one after the other, so the result is the same.
+pub(crate) fn build_nodes_parallel(sequences: &[DBVec], threads: usize) -> (Vec<Node>, u32) {
+	let mut order: Vec<usize> = (0..sequences.len()).collect();
+	build_subtrie_parallel(sequences, &mut order, 0, threads)
+}
+
+// the parts of a node, found from the indices of its sequences
+enum Split {
+	Leaf(Node),
+	Inner {
+		prefix: DBVec,
+		positions: DBVec,
+		nr_zeros: usize,    // "order" now starts with the indices that go left
+		child_offset: u64   // where the prefixes of the children start
+	}
+}
+
+// finds the prefix of the sequences in "order" from bit "offset" on; if they are
+// not all equal, also the positions, and sorts "order" stably on the bit after the prefix.
+// "ones" is scratch space, so it is not allocated for every node.
+fn split(sequences: &[DBVec], order: &mut [usize], offset: u64, ones: &mut Vec<usize>) -> Split {
+	let first = &sequences[order[0]];
+	let mut prefix_len = first.len() - offset;
+	let mut all_equal = true;
+	for &sequence_nr in &order[1..] {
+		let sequence = &sequences[sequence_nr];
+		prefix_len = common_len(first, sequence, offset, prefix_len);
+		all_equal &= sequence.len() == first.len();
+	}
+	let prefix = bit_range(first, offset, prefix_len);
+	all_equal &= prefix_len == first.len() - offset;
+	if all_equal {
+		return Split::Leaf(Node::leaf(prefix, Positions::from_elem(order.len() as u64, false)));
+	}
+
+	// split on the bit after the prefix, keeping the order on both sides
+	let split_at = offset + prefix_len;
+	let mut positions = DBVec::new();
+	let mut nr_zeros = 0;
+	ones.clear();
+	for index in 0..order.len() {
+		let sequence_nr = order[index];
+		let sequence = &sequences[sequence_nr];
+		assert!(split_at < sequence.len(), "The sequences must be prefix free: no sequence can be a prefix of another one.");
+		let bit = sequence.get(split_at);
+		positions.push(bit);
+		if bit {
+			ones.push(sequence_nr);
+		} else {
+			order[nr_zeros] = sequence_nr;
+			nr_zeros += 1;
+		}
+	}
+	order[nr_zeros..].copy_from_slice(ones);
+	Split::Inner {
+		prefix,
+		positions,
+		nr_zeros,
+		child_offset: split_at + 1
+	}
+}
+
+// builds the subtrie of the sequences in "order", whose prefix starts at bit "offset"
+pub(crate) fn build_subtrie(sequences: &[DBVec], order: &mut [usize], offset: u64) -> (Vec<Node>, u32) {
+	enum Step {
+		Build(usize, usize, u64),   // the range of "order" with the sequences of a node, and where its prefix starts
+		Finish(DBVec, DBVec)        // the prefix and positions of a node whose children are built
+	}
+	let mut ones: Vec<usize> = Vec::new();
+	let mut nodes = Vec::new();
+	let mut built: Vec<u32> = Vec::new();
+	let mut steps = vec![Step::Build(0, order.len(), offset)];
+	while let Some(step) = steps.pop() {
+		match step {
+			Step::Build(start, end, offset) => {
+				match split(sequences, &mut order[start..end], offset, &mut ones) {
+					Split::Leaf(leaf) => built.push(push_node(&mut nodes, leaf)),
+					Split::Inner { prefix, positions, nr_zeros, child_offset } => {
+						// the left child is built first, so it is below the right one on the stack
+						steps.push(Step::Finish(prefix, positions));
+						steps.push(Step::Build(start + nr_zeros, end, child_offset));
+						steps.push(Step::Build(start, start + nr_zeros, child_offset));
+					}
+				}
+			},
+			Step::Finish(prefix, positions) => {
+				let right_child = built.pop().unwrap();
+				let left_child = built.pop().unwrap();
+				built.push(push_node(&mut nodes, Node {
+					prefix,
+					positions: Positions::from_dbvec(positions),
+					children: Some((left_child, right_child))
+				}));
+			}
+		}
+	}
+	let root = built.pop().unwrap();
+	(nodes, root)
+}
+
+fn build_subtrie_parallel(sequences: &[DBVec], order: &mut [usize], offset: u64, threads: usize) -> (Vec<Node>, u32) {
+	if threads < 2 || order.len() < PARALLEL_THRESHOLD {
+		return build_subtrie(sequences, order, offset);
+	}
+	let (prefix, positions, nr_zeros, child_offset) = match split(sequences, order, offset, &mut Vec::new()) {
+		Split::Leaf(leaf) => {
+			let mut nodes = Vec::new();
+			let root = push_node(&mut nodes, leaf);
+			return (nodes, root);
+		},
+		Split::Inner { prefix, positions, nr_zeros, child_offset } => (prefix, positions, nr_zeros, child_offset)
+	};
+	let (left_order, right_order) = order.split_at_mut(nr_zeros);
+	let left_threads = threads / 2;
+	let ((mut nodes, left_child), (right_nodes, right_child)) = thread::scope(|scope| {
+		let left = scope.spawn(|| build_subtrie_parallel(sequences, left_order, child_offset, left_threads));
+		let right = build_subtrie_parallel(sequences, right_order, child_offset, threads - left_threads);
+		// a panic in the thread goes on here, with its own message
+		(left.join().unwrap_or_else(|err| panic::resume_unwind(err)), right)
+	});
+
+	// the right subtrie comes after the left one, so its indices shift
+	let shift = nodes.len() as u32;
+	for mut node in right_nodes {
+		node.children = node.children.map(|(left, right)| (left + shift, right + shift));
+		push_node(&mut nodes, node);
+	}
+	let root = push_node(&mut nodes, Node {
+		prefix,
+		positions: Positions::from_dbvec(positions),
+		children: Some((left_child, right_child + shift))
+	});
+	(nodes, root)
+}
+
+// the number of bits "first" and "second" have in common from bit "offset" on, at most max_len
+fn common_len(first: &DBVec, second: &DBVec, offset: u64, max_len: u64) -> u64 {
+	let max_len = cmp::min(max_len, second.len().saturating_sub(offset));
+	let mut len = 0;
+	while len < max_len && first.get(offset + len) == second.get(offset + len) {
+		len += 1;
+	}
+	len
+}
+
+// "len" bits of "bits", from bit "offset" on
+pub(crate) fn bit_range(bits: &DBVec, offset: u64, len: u64) -> DBVec {
+	let mut range = DBVec::new();
+	for bit_nr in offset..offset + len {
+		range.push(bits.get(bit_nr));
+	}
+	range
+}
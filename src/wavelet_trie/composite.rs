@@ -0,0 +1,338 @@
+extern crate dyn_bit_vec;
+
+use self::dyn_bit_vec::DBVec;
+use wavelet_trie::{WaveletTrie, DecodeError};
+
+// a zero byte in a text field is escaped as ESCAPE_MARK, ESCAPED_ZERO;
+// a text field ends with ESCAPE_MARK, FIELD_END.
+const ESCAPE_MARK: u8 = 0x00;
+const ESCAPED_ZERO: u8 = 0xFF;
+const FIELD_END: u8 = 0x00;
+
+// the type of a field in a composite key
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum FieldType {
+	Str,   // UTF-8 text of any length
+	U64    // unsigned number, stored as 8 big-endian bytes
+}
+
+// the value of a field in a composite key
+#[derive(Clone, Debug, PartialEq)]
+pub enum Field {
+	Str(String),
+	U64(u64)
+}
+
+impl Field {
+	pub fn field_type(&self) -> FieldType {
+		match *self {
+			Field::Str(_) => FieldType::Str,
+			Field::U64(_) => FieldType::U64
+		}
+	}
+
+	// appends the prefix-free encoding of this field to "bytes"
+	fn encode_into(&self, bytes: &mut Vec<u8>) {
+		match *self {
+			Field::Str(ref text) => {
+				encode_text_into(text, bytes);
+				bytes.push(ESCAPE_MARK);
+				bytes.push(FIELD_END);
+			},
+			Field::U64(number) => {
+				for shift in (0..8).rev() {
+					bytes.push((number >> (shift * 8)) as u8);
+				}
+			}
+		}
+	}
+}
+
+// appends the escaped text, without field end, to "bytes"
+fn encode_text_into(text: &str, bytes: &mut Vec<u8>) {
+	for byte in text.bytes() {
+		if byte == ESCAPE_MARK {
+			bytes.push(ESCAPE_MARK);
+			bytes.push(ESCAPED_ZERO);
+		} else {
+			bytes.push(byte);
+		}
+	}
+}
+
+/// A key made of several fields, e.g. (host, path, status).
+///
+/// ```rust
+/// extern crate wavelet_trie;
+///
+/// use wavelet_trie::wavelet_trie::composite::CompositeKey;
+///
+/// let key = CompositeKey::new().with_str("ugent.be").with_str("/api/users").with_u64(200);
+/// assert_eq!(3, key.fields().len());
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompositeKey {
+	fields: Vec<Field>
+}
+
+impl Default for CompositeKey {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl CompositeKey {
+
+	// constructor; add fields with the with_* methods
+	pub fn new() -> Self {
+		CompositeKey {
+			fields: Vec::new()
+		}
+	}
+
+	pub fn with_str(mut self, text: &str) -> Self {
+		self.fields.push(Field::Str(String::from(text)));
+		self
+	}
+
+	pub fn with_u64(mut self, number: u64) -> Self {
+		self.fields.push(Field::U64(number));
+		self
+	}
+
+	pub fn fields(&self) -> &[Field] {
+		&self.fields
+	}
+
+	fn encode(&self) -> DBVec {
+		let mut bytes = Vec::new();
+		for field in &self.fields {
+			field.encode_into(&mut bytes);
+		}
+		DBVec::from_bytes(&bytes)
+	}
+}
+
+// one field of a query
+#[derive(Clone, Debug, PartialEq)]
+enum QueryField {
+	Exact(Field),      // the field has exactly this value
+	StrPrefix(String)  // the (text) field starts with this value
+}
+
+/// A query on the leading fields of a composite key: the first fields must be
+/// equal to a given value, and the last one may be a prefix of a text field.
+///
+/// "All rows with host = ugent.be and path starting with /api":
+///
+/// ```rust
+/// extern crate wavelet_trie;
+///
+/// use wavelet_trie::wavelet_trie::composite::KeyQuery;
+///
+/// let query = KeyQuery::new().with_str("ugent.be").with_str_prefix("/api");
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyQuery {
+	fields: Vec<QueryField>
+}
+
+impl Default for KeyQuery {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl KeyQuery {
+
+	// constructor; an empty query matches every key
+	pub fn new() -> Self {
+		KeyQuery {
+			fields: Vec::new()
+		}
+	}
+
+	pub fn with_str(mut self, text: &str) -> Self {
+		self.fields.push(QueryField::Exact(Field::Str(String::from(text))));
+		self
+	}
+
+	pub fn with_u64(mut self, number: u64) -> Self {
+		self.fields.push(QueryField::Exact(Field::U64(number)));
+		self
+	}
+
+	// only allowed as the last field of a query
+	pub fn with_str_prefix(mut self, text: &str) -> Self {
+		self.fields.push(QueryField::StrPrefix(String::from(text)));
+		self
+	}
+
+	// encodes the query as a prefix of the encoded keys it matches.
+	// returns None if the query does not fit the schema.
+	fn encode(&self, schema: &[FieldType]) -> Option<DBVec> {
+		if self.fields.len() > schema.len() {
+			return None;
+		}
+		let mut bytes = Vec::new();
+		for (nr, (field, field_type)) in self.fields.iter().zip(schema.iter()).enumerate() {
+			match *field {
+				QueryField::Exact(ref value) => {
+					if value.field_type() != *field_type {
+						return None;
+					}
+					value.encode_into(&mut bytes);
+				},
+				QueryField::StrPrefix(ref text) => {
+					if *field_type != FieldType::Str || nr + 1 != self.fields.len() {
+						return None;
+					}
+					encode_text_into(text, &mut bytes);
+				}
+			}
+		}
+		Some(DBVec::from_bytes(&bytes))
+	}
+}
+
+/// A wavelet trie holding composite keys that all follow the same schema.
+///
+/// Each field is encoded in a prefix-free way and the encodings are
+/// concatenated, so a query on a leading subset of the fields is an ordinary
+/// prefix query on the trie.
+///
+/// # Examples
+///
+/// ```rust
+/// extern crate wavelet_trie;
+///
+/// use wavelet_trie::wavelet_trie::composite::{CompositeWaveletTrie, CompositeKey, KeyQuery, FieldType, Field};
+///
+/// let mut wt = CompositeWaveletTrie::new(&[FieldType::Str, FieldType::Str, FieldType::U64]);
+/// wt.append(&CompositeKey::new().with_str("ugent.be").with_str("/api/users").with_u64(200)).unwrap();
+/// wt.append(&CompositeKey::new().with_str("ugent.be").with_str("/index.html").with_u64(404)).unwrap();
+/// wt.append(&CompositeKey::new().with_str("example.org").with_str("/api/users").with_u64(200)).unwrap();
+///
+/// let query = KeyQuery::new().with_str("ugent.be").with_str_prefix("/api");
+/// assert_eq!(vec![0], wt.select_all(&query));
+///
+/// let fields = wt.access(1).unwrap();
+/// assert_eq!(Field::U64(404), fields[2]);
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CompositeWaveletTrie {
+	schema: Vec<FieldType>,
+	trie: WaveletTrie
+}
+
+impl CompositeWaveletTrie {
+
+	// constructor
+	pub fn new(schema: &[FieldType]) -> Self {
+		CompositeWaveletTrie {
+			schema: schema.to_vec(),
+			trie: WaveletTrie::new()
+		}
+	}
+
+	pub fn schema(&self) -> &[FieldType] {
+		&self.schema
+	}
+
+	pub fn len(&self) -> u64 {
+		self.trie.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	// appends a key to the trie at last position
+	pub fn append(&mut self, key: &CompositeKey) -> Result<(), &'static str> {
+		let index = self.len();
+		self.insert(key, index)
+	}
+
+	pub fn insert(&mut self, key: &CompositeKey, index: u64) -> Result<(), &'static str> {
+		let fits_schema = key.fields.len() == self.schema.len()
+			&& key.fields.iter().zip(self.schema.iter()).all(|(field, field_type)| field.field_type() == *field_type);
+		if !fits_schema {
+			return Err("The key does not match the schema of the trie.");
+		}
+		self.trie.insert(&key.encode(), index)
+	}
+
+	pub fn delete(&mut self, index: u64) {
+		self.trie.delete(index);
+	}
+
+	// retrieves the fields of the key at the given index
+	pub fn access(&self, index: u64) -> Result<Vec<Field>, DecodeError> {
+		let sequence = self.trie.access(index);
+		if !sequence.len().is_multiple_of(8) {
+			return Err(DecodeError::InvalidLayout);
+		}
+		let bytes = sequence.to_bytes();
+		let mut fields = Vec::with_capacity(self.schema.len());
+		let mut pos = 0;
+		for field_type in &self.schema {
+			match *field_type {
+				FieldType::Str => {
+					let mut text = Vec::new();
+					loop {
+						match (bytes.get(pos), bytes.get(pos + 1)) {
+							(Some(&ESCAPE_MARK), Some(&FIELD_END)) => {
+								pos += 2;
+								break;
+							},
+							(Some(&ESCAPE_MARK), Some(&ESCAPED_ZERO)) => {
+								text.push(0);
+								pos += 2;
+							},
+							(Some(&ESCAPE_MARK), _) | (None, _) => return Err(DecodeError::InvalidLayout),
+							(Some(&byte), _) => {
+								text.push(byte);
+								pos += 1;
+							}
+						}
+					}
+					fields.push(Field::Str(String::from_utf8(text).map_err(DecodeError::InvalidUtf8)?));
+				},
+				FieldType::U64 => {
+					if pos + 8 > bytes.len() {
+						return Err(DecodeError::InvalidLayout);
+					}
+					let number = bytes[pos..pos + 8].iter().fold(0u64, |number, byte| (number << 8) | *byte as u64);
+					fields.push(Field::U64(number));
+					pos += 8;
+				}
+			}
+		}
+		if pos != bytes.len() {
+			return Err(DecodeError::InvalidLayout);
+		}
+		Ok(fields)
+	}
+
+	// counts the number of keys matching the query up to index - 1.
+	// returns None if no key matches, or if the query does not fit the schema
+	pub fn rank(&self, query: &KeyQuery, index: u64) -> Option<u64> {
+		query.encode(&self.schema).and_then(|sequence| self.trie.rank(&sequence, index))
+	}
+
+	// finds the position of the occurrence_nr-th key matching the query
+	// an occurrence number starts at 1 (a zero-th occurrence makes no sense)
+	// returns None if not found, or if the query does not fit the schema
+	pub fn select(&self, query: &KeyQuery, occurrence_nr: u64) -> Option<u64> {
+		query.encode(&self.schema).and_then(|sequence| self.trie.select(&sequence, occurrence_nr))
+	}
+
+	// finds the positions of all keys matching the query.
+	// a query that does not fit the schema matches nothing
+	pub fn select_all(&self, query: &KeyQuery) -> Vec<u64> {
+		match query.encode(&self.schema) {
+			Some(sequence) => self.trie.select_all(&sequence),
+			None => Vec::new()
+		}
+	}
+}
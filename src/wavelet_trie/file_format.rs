@@ -0,0 +1,736 @@
+// The file format of a wavelet trie, as written by WaveletTrie::save and read
+// by WaveletTrie::load. All integers are little endian.
+//
+// offset  size  field
+//      0     8  magic bytes "WAVETRIE"
+//      8     2  format version (currently 1)
+//     10     2  flags; the lowest 8 bits give the payload encoding, the other bits are 0
+//     12     4  reserved, 0
+//     16     8  length: the number of sequences in the trie
+//     24     8  the number of nodes in the trie
+//     32     8  payload length in bytes (n)
+//     40     n  payload
+//   40+n     4  CRC-32 (IEEE) of all preceding bytes
+//
+// Payload encoding 0 (node list): the nodes in pre-order (node, left subtrie,
+// right subtrie). Every node is written as
+//
+//   1 byte   0 for a leaf, 1 for a node with children
+//   8 bytes  number of bits in the prefix (p)
+//   ⌈p/8⌉    prefix bits
+//   8 bytes  number of bits in the positions (l)
+//   ⌈l/8⌉    position bits; left out for leaves, whose positions are all 0
+//
+// Payload encoding 1 (succinct): the shape of the trie and the bits of all nodes,
+// each concatenated into one bit section. The nodes are numbered in level order
+// (breadth first, left before right).
+//
+//   8 bytes  total number of prefix bits (P)
+//   1 byte   width w of a prefix offset, the number of bits needed to write P
+//   section  shape: one bit per node, 1 for a node with children, 0 for a leaf
+//   section  prefix ends: per node, the offset in the prefix section where its prefix ends (w bits each)
+//   section  prefixes: the prefixes of all nodes, P bits
+//   section  positions: the positions of all nodes with children
+//
+// Every section starts at a byte boundary. The length of the positions of a node
+// is not stored: the root has "length" positions, and the children of a node
+// have as many positions as there are 0s (left) and 1s (right) in its positions.
+//
+// Bit i of a bit sequence is stored in byte i / 8, at bit i % 8 (least significant
+// bit first). Unused bits in the last byte are 0. Within the succinct encoding,
+// numbers of w bits are stored the same way, least significant bit first.
+//
+// Payload encoding 2 (flat): a layout made for querying the file in place,
+// without loading it. See mapped.rs for its description.
+//
+// A reader must refuse files with a newer format version or unknown flags,
+// and must check the checksum before decoding the payload.
+
+extern crate dyn_bit_vec;
+extern crate crc32fast;
+
+use self::dyn_bit_vec::DBVec;
+use self::crc32fast::Hasher;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::io::{Read, Write};
+use wavelet_trie::{WaveletTrie, Node, push_node};
+use wavelet_trie::positions::Positions;
+use wavelet_trie::mapped::FlatView;
+
+pub const MAGIC: &[u8; 8] = b"WAVETRIE";
+pub const FORMAT_VERSION: u16 = 1;
+pub const ENCODING_NODE_LIST: u16 = 0;
+pub const ENCODING_SUCCINCT: u16 = 1;
+pub const ENCODING_FLAT: u16 = 2;
+
+const ENCODING_MASK: u16 = 0x00FF;
+pub(crate) const HEADER_LEN: usize = 40;
+pub(crate) const NODE_LEAF: u8 = 0;
+pub(crate) const NODE_INNER: u8 = 1;
+
+// reasons why a wavelet trie cannot be read from (or written to) a file
+#[derive(Debug)]
+pub enum FormatError {
+	Io(io::Error),                                  // reading or writing failed
+	BadMagic,                                       // this is not a wavelet trie file
+	UnsupportedVersion(u16),                        // written by a newer version of this library
+	UnsupportedFlags(u16),                          // unknown encoding or flags
+	ChecksumMismatch { expected: u32, found: u32 }, // the file is damaged
+	Corrupt(&'static str)                           // the checksum is right, but the content is not
+}
+
+impl fmt::Display for FormatError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			FormatError::Io(ref err) => write!(f, "I/O error: {}", err),
+			FormatError::BadMagic => write!(f, "Not a wavelet trie file (bad magic bytes)."),
+			FormatError::UnsupportedVersion(version) => write!(f, "Unsupported format version {} (at most {} is supported).", version, FORMAT_VERSION),
+			FormatError::UnsupportedFlags(flags) => write!(f, "Unsupported flags {:#06x}.", flags),
+			FormatError::ChecksumMismatch { expected, found } => write!(f, "Checksum mismatch: expected {:#010x}, found {:#010x}. The file is damaged.", expected, found),
+			FormatError::Corrupt(reason) => write!(f, "Corrupt wavelet trie file: {}", reason)
+		}
+	}
+}
+
+impl Error for FormatError {}
+
+// how the nodes of a trie are written in a file
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+	NodeList,   // node by node, with their lengths; simple
+	Succinct,   // shape as bits, all prefixes and positions concatenated; small
+	Flat        // fixed-size node records and rank/select directories; can be memory-mapped
+}
+
+impl From<io::Error> for FormatError {
+	fn from(err: io::Error) -> Self {
+		FormatError::Io(err)
+	}
+}
+
+impl WaveletTrie {
+
+	// writes the trie in the file format described at the top of file_format.rs,
+	// using the node list encoding
+	pub fn save(&self, writer: &mut dyn Write) -> Result<(), FormatError> {
+		self.save_with(writer, Encoding::NodeList)
+	}
+
+	// writes the trie in the file format described at the top of file_format.rs
+	pub fn save_with(&self, writer: &mut dyn Write, encoding: Encoding) -> Result<(), FormatError> {
+		let (encoding_flag, payload, node_count) = match encoding {
+			Encoding::NodeList => {
+				let mut payload = Vec::new();
+				let node_count = self.write_node_list(&mut payload, None)?;
+				(ENCODING_NODE_LIST, payload, node_count)
+			},
+			Encoding::Succinct => {
+				let (payload, node_count) = self.encode_succinct();
+				(ENCODING_SUCCINCT, payload, node_count)
+			},
+			Encoding::Flat => {
+				let (payload, node_count) = self.encode_flat();
+				(ENCODING_FLAT, payload, node_count)
+			}
+		};
+		let header = encode_header(encoding_flag, self.len(), node_count, payload.len() as u64);
+		let mut hasher = Hasher::new();
+		hasher.update(&header);
+		hasher.update(&payload);
+		writer.write_all(&header)?;
+		writer.write_all(&payload)?;
+		write_u32(writer, hasher.finalize())?;
+		Ok(())
+	}
+
+	// reads a trie written by save or save_with. The checksum and the structure of
+	// the trie are verified; a damaged file gives an error, never a broken trie.
+	pub fn load(reader: &mut dyn Read) -> Result<Self, FormatError> {
+		let mut header = [0u8; HEADER_LEN];
+		reader.read_exact(&mut header)?;
+		let header_info = decode_header(&header)?;
+		if header_info.encoding > ENCODING_FLAT {
+			return Err(FormatError::UnsupportedFlags(header_info.encoding));
+		}
+
+		// read the payload without trusting the length for a single allocation
+		let mut payload = Vec::new();
+		Read::take(&mut *reader, header_info.payload_len).read_to_end(&mut payload)?;
+		if (payload.len() as u64) < header_info.payload_len {
+			return Err(FormatError::Io(io::Error::new(io::ErrorKind::UnexpectedEof, "payload is truncated")));
+		}
+		let expected = read_u32(reader)?;
+		let mut hasher = Hasher::new();
+		hasher.update(&header);
+		hasher.update(&payload);
+		let found = hasher.finalize();
+		if expected != found {
+			return Err(FormatError::ChecksumMismatch { expected, found });
+		}
+
+		let trie = if header_info.encoding == ENCODING_SUCCINCT {
+			WaveletTrie::decode_succinct(&payload, header_info.node_count, header_info.length)?
+		} else if header_info.encoding == ENCODING_FLAT {
+			let view = FlatView::new(&payload)?;
+			if view.nr_nodes() != header_info.node_count {
+				return Err(FormatError::Corrupt("the number of nodes does not match the header"));
+			}
+			view.to_trie()
+		} else {
+			// the whole payload is there, so running out of it means the content is wrong
+			let mut rest = &payload[..];
+			let (nodes, root) = match WaveletTrie::read_node_list(&mut rest, header_info.node_count, None) {
+				Err(FormatError::Io(ref err)) if err.kind() == io::ErrorKind::UnexpectedEof => return Err(FormatError::Corrupt("unexpected end of data")),
+				result => result?
+			};
+			if !rest.is_empty() {
+				return Err(FormatError::Corrupt("trailing bytes after the last node"));
+			}
+			if nodes.len() as u64 != header_info.node_count {
+				return Err(FormatError::Corrupt("the number of nodes does not match the header"));
+			}
+			WaveletTrie::from_nodes(nodes, root)
+		};
+		if trie.len() != header_info.length {
+			return Err(FormatError::Corrupt("the number of sequences does not match the header"));
+		}
+		Ok(trie)
+	}
+
+	// the length of the node list encoding of the trie in bytes, and the number of nodes
+	pub(crate) fn node_list_len(&self) -> (u64, u64) {
+		let mut payload_len = 0;
+		let mut node_count = 0;
+		let mut stack = vec![self.root_node()];
+		while let Some(node) = stack.pop() {
+			node_count += 1;
+			payload_len += 1 + 8 + node.prefix.len().div_ceil(8) + 8;
+			if let Some((left, right)) = node.children {
+				payload_len += node.positions.len().div_ceil(8);
+				stack.push(self.node(right));
+				stack.push(self.node(left));
+			}
+		}
+		(payload_len, node_count)
+	}
+
+	// writes the nodes in pre-order, with a stack instead of recursion so deep
+	// tries fit. "progress" is called after every node with the number of nodes
+	// and bytes written so far. Returns the number of nodes written
+	pub(crate) fn write_node_list(&self, out: &mut dyn Write, mut progress: Option<&mut dyn FnMut(u64, u64)>) -> io::Result<u64> {
+		let mut out = Counted::new(out);
+		let mut node_count = 0;
+		let mut stack = vec![self.root_node()];
+		while let Some(node) = stack.pop() {
+			out.write_all(&[if node.is_leaf() { NODE_LEAF } else { NODE_INNER }])?;
+			write_bits(&mut out, &node.prefix)?;
+			match node.children {
+				None => out.write_all(&u64_to_bytes(node.positions.len()))?,
+				Some((left, right)) => {
+					write_bits(&mut out, &node.positions.bits())?;
+					stack.push(self.node(right));
+					stack.push(self.node(left));
+				}
+			}
+			node_count += 1;
+			if let Some(progress) = progress.as_mut() {
+				progress(node_count, out.bytes);
+			}
+		}
+		Ok(node_count)
+	}
+
+	// reads the nodes written by write_node_list; a node is added once both its
+	// subtries are, so children come before their parent. More than max_nodes
+	// nodes is an error. "progress" is called after every node with the number of
+	// nodes and bytes read so far. Returns the nodes and the index of the root.
+	pub(crate) fn read_node_list(input: &mut dyn Read, max_nodes: u64, mut progress: Option<&mut dyn FnMut(u64, u64)>) -> Result<(Vec<Node>, u32), FormatError> {
+		let mut input = Counted::new(input);
+		// inner nodes whose children are being read, with the index of the left child once it is complete
+		let mut stack: Vec<(Node, Option<u32>)> = Vec::new();
+		let mut nodes = Vec::new();
+		let mut node_count = 0;
+		loop {
+			let mut tag = [0u8; 1];
+			input.read_exact(&mut tag)?;
+			let prefix = read_bits(&mut input)?;
+			let positions = match tag[0] {
+				NODE_LEAF => Positions::from_elem(read_u64(&mut input)?, false),
+				NODE_INNER => Positions::from_dbvec(read_bits(&mut input)?),
+				_ => return Err(FormatError::Corrupt("unknown node type"))
+			};
+			node_count += 1;
+			if node_count > max_nodes {
+				return Err(FormatError::Corrupt("the number of nodes does not match the header"));
+			}
+			if let Some(progress) = progress.as_mut() {
+				progress(node_count, input.bytes);
+			}
+			let node = Node::leaf(prefix, positions);
+			if tag[0] == NODE_INNER {
+				stack.push((node, None));
+				continue;
+			}
+
+			// a complete node: hang it under its parent, and complete the parents that have both children now
+			let mut complete = push_node(&mut nodes, node);
+			loop {
+				match stack.pop() {
+					None => return Ok((nodes, complete)),
+					Some((parent, None)) => {
+						stack.push((parent, Some(complete)));
+						break;
+					},
+					Some((mut parent, Some(left))) => {
+						check_children(&parent.positions.bits(), &nodes[left as usize], &nodes[complete as usize])?;
+						parent.children = Some((left, complete));
+						complete = push_node(&mut nodes, parent);
+					}
+				}
+			}
+		}
+	}
+
+	// the nodes in level order (breadth first, left before right)
+	pub(crate) fn level_order(&self) -> Vec<&Node> {
+		let mut nodes: Vec<&Node> = Vec::new();
+		let mut queue = VecDeque::new();
+		queue.push_back(self.root_node());
+		while let Some(node) = queue.pop_front() {
+			nodes.push(node);
+			if let Some((left, right)) = node.children {
+				queue.push_back(self.node(left));
+				queue.push_back(self.node(right));
+			}
+		}
+		nodes
+	}
+
+	// returns the succinct payload and the number of nodes
+	fn encode_succinct(&self) -> (Vec<u8>, u64) {
+		let nodes = self.level_order();
+
+		let total_prefix_bits: u64 = nodes.iter().map(|node| node.prefix.len()).sum();
+		let offset_width = 64 - total_prefix_bits.leading_zeros();
+
+		let mut payload = Vec::new();
+		payload.extend_from_slice(&u64_to_bytes(total_prefix_bits));
+		payload.push(offset_width as u8);
+		let mut shape = BitWriter::new();
+		let mut prefix_ends = BitWriter::new();
+		let mut prefixes = BitWriter::new();
+		let mut positions = BitWriter::new();
+		for node in &nodes {
+			let is_leaf = node.is_leaf();
+			shape.push_bit(!is_leaf);
+			prefixes.push_bits(&node.prefix);
+			prefix_ends.push_value(prefixes.len(), offset_width);
+			if !is_leaf {
+				positions.push_bits(&node.positions.bits());
+			}
+		}
+		for section in [shape, prefix_ends, prefixes, positions] {
+			payload.extend_from_slice(&section.into_bytes());
+		}
+		(payload, nodes.len() as u64)
+	}
+
+	fn decode_succinct(payload: &[u8], node_count: u64, length: u64) -> Result<Self, FormatError> {
+		if node_count == 0 || node_count > payload.len() as u64 * 8 {
+			return Err(FormatError::Corrupt("the number of nodes does not match the payload"));
+		}
+		let mut cursor = ByteCursor::new(payload);
+		let total_prefix_bits = cursor.read_u64()?;
+		let offset_width = cursor.read_u8()? as u32;
+		if offset_width > 64 || (offset_width < 64 && total_prefix_bits >> offset_width != 0) {
+			return Err(FormatError::Corrupt("the prefix offset width is wrong"));
+		}
+		let shape = cursor.read_bytes(node_count.div_ceil(8))?;
+		let prefix_ends = cursor.read_bytes((node_count * offset_width as u64).div_ceil(8))?;
+		let prefixes = cursor.read_bytes(total_prefix_bits.div_ceil(8))?;
+		let mut shape = BitReader::new(shape);
+		let mut prefix_ends = BitReader::new(prefix_ends);
+		let mut prefixes = BitReader::new(prefixes);
+
+		// first pass: the shape, the prefixes and the number of positions of each node
+		let mut is_inner = Vec::new();
+		let mut node_prefixes = Vec::new();
+		let mut node_lengths = vec![length];
+		let mut prefix_start = 0;
+		for node_nr in 0..node_count as usize {
+			if node_nr >= node_lengths.len() {
+				return Err(FormatError::Corrupt("the shape has more nodes than it can reach"));
+			}
+			let prefix_end = prefix_ends.read_value(offset_width)?;
+			if prefix_end < prefix_start || prefix_end > total_prefix_bits {
+				return Err(FormatError::Corrupt("prefix offsets are out of order"));
+			}
+			node_prefixes.push(prefixes.read_bits(prefix_end - prefix_start)?);
+			prefix_start = prefix_end;
+			let inner = shape.read_bit()?;
+			if inner {
+				// the lengths of the children follow from the positions; put 0 for now
+				node_lengths.push(0);
+				node_lengths.push(0);
+			}
+			is_inner.push(inner);
+		}
+		if node_lengths.len() as u64 != node_count || prefix_start != total_prefix_bits {
+			return Err(FormatError::Corrupt("the shape does not match the number of nodes"));
+		}
+
+		// second pass: the positions, in level order, which give the lengths of the children
+		let mut positions = BitReader::new(cursor.read_bytes(cursor.remaining())?);
+		let mut node_positions = Vec::with_capacity(node_count as usize);
+		let mut next_child = 1;
+		for node_nr in 0..node_count as usize {
+			if is_inner[node_nr] {
+				let node_positions_here = positions.read_bits(node_lengths[node_nr])?;
+				let zeros = node_positions_here.rank(false, node_positions_here.len());
+				node_lengths[next_child] = zeros;
+				node_lengths[next_child + 1] = node_positions_here.len() - zeros;
+				next_child += 2;
+				node_positions.push(Positions::from_dbvec(node_positions_here));
+			} else {
+				node_positions.push(Positions::from_elem(node_lengths[node_nr], false));
+			}
+		}
+		if !positions.is_at_padding() {
+			return Err(FormatError::Corrupt("trailing bytes after the positions"));
+		}
+
+		// the nodes keep their level order in the arena, so the root is node 0
+		let mut nodes = Vec::with_capacity(node_count as usize);
+		for (prefix, positions) in node_prefixes.into_iter().zip(node_positions) {
+			push_node(&mut nodes, Node::leaf(prefix, positions));
+		}
+		let mut next_child = 1;
+		for node_nr in 0..node_count as usize {
+			if is_inner[node_nr] {
+				check_children(&nodes[node_nr].positions.bits(), &nodes[next_child], &nodes[next_child + 1])?;
+				nodes[node_nr].children = Some((next_child as u32, next_child as u32 + 1));
+				next_child += 2;
+			}
+		}
+		Ok(WaveletTrie::from_nodes(nodes, 0))
+	}
+}
+
+// the fixed-size part at the start of a file
+pub(crate) struct HeaderInfo {
+	pub encoding: u16,
+	pub length: u64,
+	pub node_count: u64,
+	pub payload_len: u64
+}
+
+pub(crate) fn encode_header(encoding: u16, length: u64, node_count: u64, payload_len: u64) -> [u8; HEADER_LEN] {
+	let mut header = [0u8; HEADER_LEN];
+	header[0..8].copy_from_slice(MAGIC);
+	header[8..10].copy_from_slice(&u16_to_bytes(FORMAT_VERSION));
+	header[10..12].copy_from_slice(&u16_to_bytes(encoding & ENCODING_MASK));
+	header[16..24].copy_from_slice(&u64_to_bytes(length));
+	header[24..32].copy_from_slice(&u64_to_bytes(node_count));
+	header[32..40].copy_from_slice(&u64_to_bytes(payload_len));
+	header
+}
+
+pub(crate) fn decode_header(header: &[u8; HEADER_LEN]) -> Result<HeaderInfo, FormatError> {
+	if &header[0..8] != MAGIC {
+		return Err(FormatError::BadMagic);
+	}
+	let version = bytes_to_u16(&header[8..10]);
+	if version == 0 || version > FORMAT_VERSION {
+		return Err(FormatError::UnsupportedVersion(version));
+	}
+	let flags = bytes_to_u16(&header[10..12]);
+	if flags & !ENCODING_MASK != 0 || bytes_to_u32(&header[12..16]) != 0 {
+		return Err(FormatError::UnsupportedFlags(flags));
+	}
+	Ok(HeaderInfo {
+		encoding: flags & ENCODING_MASK,
+		length: bytes_to_u64(&header[16..24]),
+		node_count: bytes_to_u64(&header[24..32]),
+		payload_len: bytes_to_u64(&header[32..40])
+	})
+}
+
+// the positions of a node with children must be distributed over exactly its children
+pub(crate) fn check_children(positions: &DBVec, left: &Node, right: &Node) -> Result<(), FormatError> {
+	let zeros = positions.rank(false, positions.len());
+	if zeros != left.len() || positions.len() - zeros != right.len() || left.len() == 0 || right.len() == 0 {
+		Err(FormatError::Corrupt("the positions of a node do not match its children"))
+	} else {
+		Ok(())
+	}
+}
+
+// the bits of "bits" as bytes, least significant bit first, unused bits 0
+pub(crate) fn bits_to_bytes(bits: &DBVec) -> Vec<u8> {
+	let nr_bytes = bits.len().div_ceil(8) as usize;
+	let mut bytes = bits.to_bytes();
+	bytes.resize(nr_bytes, 0);
+	if !bits.len().is_multiple_of(8) {
+		bytes[nr_bytes - 1] &= (1u8 << (bits.len() % 8)) - 1;
+	}
+	bytes
+}
+
+// the first nr_bits bits of "bytes" as bit vector, least significant bit first
+pub(crate) fn bytes_to_bits(bytes: &[u8], nr_bits: u64) -> DBVec {
+	let full_bytes = (nr_bits / 8) as usize;
+	let mut bits = DBVec::from_bytes(&bytes[..full_bytes]);
+	for bit_nr in 0..nr_bits % 8 {
+		bits.push((bytes[full_bytes] >> bit_nr) & 1 == 1);
+	}
+	bits
+}
+
+fn write_bits(out: &mut dyn Write, bits: &DBVec) -> io::Result<()> {
+	out.write_all(&u64_to_bytes(bits.len()))?;
+	out.write_all(&bits_to_bytes(bits))
+}
+
+// reads a length in bits and the bits; the length is not trusted for a single allocation
+fn read_bits(input: &mut dyn Read) -> io::Result<DBVec> {
+	let nr_bits = read_u64(input)?;
+	let nr_bytes = nr_bits.div_ceil(8);
+	let mut bytes = Vec::new();
+	Read::take(&mut *input, nr_bytes).read_to_end(&mut bytes)?;
+	if (bytes.len() as u64) < nr_bytes {
+		return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the data is truncated"));
+	}
+	Ok(bytes_to_bits(&bytes, nr_bits))
+}
+
+fn read_u64(input: &mut dyn Read) -> io::Result<u64> {
+	let mut bytes = [0u8; 8];
+	input.read_exact(&mut bytes)?;
+	Ok(bytes_to_u64(&bytes))
+}
+
+// a reader or writer that counts the bytes that go through
+struct Counted<T> {
+	inner: T,
+	bytes: u64
+}
+
+impl<T> Counted<T> {
+	fn new(inner: T) -> Self {
+		Counted {
+			inner,
+			bytes: 0
+		}
+	}
+}
+
+impl<T: Write> Write for Counted<T> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let written = self.inner.write(buf)?;
+		self.bytes += written as u64;
+		Ok(written)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.inner.flush()
+	}
+}
+
+impl<T: Read> Read for Counted<T> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let read = self.inner.read(buf)?;
+		self.bytes += read as u64;
+		Ok(read)
+	}
+}
+
+// reads from a byte slice, failing on reads past the end
+pub(crate) struct ByteCursor<'a> {
+	bytes: &'a [u8],
+	pos: usize
+}
+
+impl<'a> ByteCursor<'a> {
+	pub fn new(bytes: &'a [u8]) -> Self {
+		ByteCursor {
+			bytes,
+			pos: 0
+		}
+	}
+
+	pub fn remaining(&self) -> u64 {
+		(self.bytes.len() - self.pos) as u64
+	}
+
+	pub fn read_bytes(&mut self, len: u64) -> Result<&'a [u8], FormatError> {
+		if len > (self.bytes.len() - self.pos) as u64 {
+			return Err(FormatError::Corrupt("unexpected end of data"));
+		}
+		let start = self.pos;
+		self.pos += len as usize;
+		Ok(&self.bytes[start..self.pos])
+	}
+
+	pub fn read_u8(&mut self) -> Result<u8, FormatError> {
+		Ok(self.read_bytes(1)?[0])
+	}
+
+	pub fn read_u64(&mut self) -> Result<u64, FormatError> {
+		Ok(bytes_to_u64(self.read_bytes(8)?))
+	}
+}
+
+// writes bits and numbers into bytes, least significant bit first
+pub(crate) struct BitWriter {
+	bytes: Vec<u8>,
+	nr_bits: u64
+}
+
+impl BitWriter {
+	pub fn new() -> Self {
+		BitWriter {
+			bytes: Vec::new(),
+			nr_bits: 0
+		}
+	}
+
+	pub fn len(&self) -> u64 {
+		self.nr_bits
+	}
+
+	pub fn push_bit(&mut self, bit: bool) {
+		if self.nr_bits.is_multiple_of(8) {
+			self.bytes.push(0);
+		}
+		if bit {
+			let last = self.bytes.len() - 1;
+			self.bytes[last] |= 1 << (self.nr_bits % 8);
+		}
+		self.nr_bits += 1;
+	}
+
+	pub fn push_value(&mut self, value: u64, width: u32) {
+		for bit_nr in 0..width {
+			self.push_bit((value >> bit_nr) & 1 == 1);
+		}
+	}
+
+	pub fn push_bits(&mut self, bits: &DBVec) {
+		let bytes = bits_to_bytes(bits);
+		if self.nr_bits.is_multiple_of(8) {
+			// aligned: copy the bytes as they are
+			self.bytes.extend_from_slice(&bytes);
+			self.nr_bits += bits.len();
+		} else {
+			for bit_nr in 0..bits.len() {
+				self.push_bit((bytes[(bit_nr / 8) as usize] >> (bit_nr % 8)) & 1 == 1);
+			}
+		}
+	}
+
+	pub fn into_bytes(self) -> Vec<u8> {
+		self.bytes
+	}
+}
+
+// reads bits and numbers written by a BitWriter
+pub(crate) struct BitReader<'a> {
+	bytes: &'a [u8],
+	bit_pos: u64
+}
+
+impl<'a> BitReader<'a> {
+	pub fn new(bytes: &'a [u8]) -> Self {
+		BitReader {
+			bytes,
+			bit_pos: 0
+		}
+	}
+
+	// true if only the padding bits of the last byte are left
+	pub fn is_at_padding(&self) -> bool {
+		(self.bytes.len() as u64) * 8 - self.bit_pos < 8
+	}
+
+	pub fn read_bit(&mut self) -> Result<bool, FormatError> {
+		if self.bit_pos >= (self.bytes.len() as u64) * 8 {
+			return Err(FormatError::Corrupt("unexpected end of data"));
+		}
+		let bit = (self.bytes[(self.bit_pos / 8) as usize] >> (self.bit_pos % 8)) & 1 == 1;
+		self.bit_pos += 1;
+		Ok(bit)
+	}
+
+	pub fn read_value(&mut self, width: u32) -> Result<u64, FormatError> {
+		let mut value = 0;
+		for bit_nr in 0..width {
+			if self.read_bit()? {
+				value |= 1 << bit_nr;
+			}
+		}
+		Ok(value)
+	}
+
+	pub fn read_bits(&mut self, nr_bits: u64) -> Result<DBVec, FormatError> {
+		if nr_bits > (self.bytes.len() as u64) * 8 - self.bit_pos {
+			return Err(FormatError::Corrupt("unexpected end of data"));
+		}
+		if self.bit_pos.is_multiple_of(8) {
+			// aligned: convert the bytes as they are
+			let start = (self.bit_pos / 8) as usize;
+			self.bit_pos += nr_bits;
+			Ok(bytes_to_bits(&self.bytes[start..], nr_bits))
+		} else {
+			let mut bits = DBVec::new();
+			for _ in 0..nr_bits {
+				bits.push(self.read_bit()?);
+			}
+			Ok(bits)
+		}
+	}
+}
+
+pub(crate) fn write_u32(writer: &mut dyn Write, value: u32) -> io::Result<()> {
+	writer.write_all(&u32_to_bytes(value))
+}
+
+pub(crate) fn read_u32(reader: &mut dyn Read) -> io::Result<u32> {
+	let mut bytes = [0u8; 4];
+	reader.read_exact(&mut bytes)?;
+	Ok(bytes_to_u32(&bytes))
+}
+
+pub(crate) fn u16_to_bytes(value: u16) -> [u8; 2] {
+	[value as u8, (value >> 8) as u8]
+}
+
+pub(crate) fn u32_to_bytes(value: u32) -> [u8; 4] {
+	let mut bytes = [0u8; 4];
+	for (i, byte) in bytes.iter_mut().enumerate() {
+		*byte = (value >> (i * 8)) as u8;
+	}
+	bytes
+}
+
+pub(crate) fn u64_to_bytes(value: u64) -> [u8; 8] {
+	let mut bytes = [0u8; 8];
+	for (i, byte) in bytes.iter_mut().enumerate() {
+		*byte = (value >> (i * 8)) as u8;
+	}
+	bytes
+}
+
+pub(crate) fn bytes_to_u16(bytes: &[u8]) -> u16 {
+	bytes[0] as u16 | (bytes[1] as u16) << 8
+}
+
+pub(crate) fn bytes_to_u32(bytes: &[u8]) -> u32 {
+	bytes[..4].iter().rev().fold(0, |value, byte| (value << 8) | *byte as u32)
+}
+
+pub(crate) fn bytes_to_u64(bytes: &[u8]) -> u64 {
+	bytes[..8].iter().rev().fold(0, |value, byte| (value << 8) | *byte as u64)
+}
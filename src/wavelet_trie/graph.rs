@@ -0,0 +1,165 @@
+// Graphviz (dot) output of the shape of a trie. Nodes are numbered in
+// pre-order, starting at 0 for the root. The nodes are visited with a stack
+// instead of recursion, so deep tries do not overflow the call stack.
+
+extern crate dyn_bit_vec;
+
+use self::dyn_bit_vec::DBVec;
+use std::cmp;
+use std::io;
+use std::io::Write;
+use wavelet_trie::{WaveletTrie, common_prefix_len};
+use wavelet_trie::json::bits_to_string;
+
+// what a node shows besides its number and sizes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GraphLabels {
+	Sparseness,     // the sparseness of the prefix and positions
+	PrefixBits,     // the prefix as '0's and '1's, bit 0 first
+	PrefixText      // what all sequences in the node start with, from the root on, as text;
+	                // bits after the last whole byte as '0's and '1's
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GraphOptions {
+	pub labels: GraphLabels,
+	pub highlight: Option<DBVec>,   // the nodes a query for this (prefix) sequence passes are highlighted
+	pub scale_by_size: bool         // the size of a node grows with its number of positions
+}
+
+impl Default for GraphOptions {
+	fn default() -> Self {
+		GraphOptions {
+			labels: GraphLabels::Sparseness,
+			highlight: None,
+			scale_by_size: false
+		}
+	}
+}
+
+impl WaveletTrie {
+
+	pub fn generate_graph(&self, out: &mut dyn Write) -> io::Result<()> {
+		self.generate_graph_with(out, &GraphOptions::default())
+	}
+
+	pub fn generate_graph_with(&self, out: &mut dyn Write, options: &GraphOptions) -> io::Result<()> {
+		// a node to write: its index, the number of its parent and the bit to it (None
+		// for the root), how long the path of the parent is, and where the rest of the
+		// query starts if the query gets here
+		struct Step {
+			id: u32,
+			parent: Option<(u64, bool)>,
+			path_len: usize,
+			query_offset: Option<u64>
+		}
+		out.write_all(b"digraph wavelet_trie {\n  graph [rankdir = \"LR\"]; node [shape = \"record\"];\n")?;
+		// the bits from the root up to and with the prefix of the current node; only kept for PrefixText
+		let mut path: Vec<bool> = Vec::new();
+		let mut next_nr = 0;
+		let mut stack = vec![Step { id: self.root, parent: None, path_len: 0, query_offset: options.highlight.as_ref().map(|_| 0) }];
+		while let Some(step) = stack.pop() {
+			let node = self.node(step.id);
+			let node_nr = next_nr;
+			next_nr += 1;
+
+			// how far the query matches the prefix: the node is highlighted if the query starts
+			// with the prefix or is a prefix of it, and goes on to a child if it is longer
+			let (is_highlighted, next_query) = match (options.highlight.as_ref(), step.query_offset) {
+				(Some(query), Some(offset)) => {
+					let rest_len = query.len() - offset;
+					let common_len = common_prefix_len(&node.prefix, query, offset);
+					let is_highlighted = common_len == cmp::min(rest_len, node.prefix.len());
+					if is_highlighted && rest_len > node.prefix.len() {
+						let split_at = offset + node.prefix.len();
+						(true, Some((query.get(split_at), split_at + 1)))
+					} else {
+						(is_highlighted, None)
+					}
+				},
+				_ => (false, None)
+			};
+
+			let mut fields = vec![node_nr.to_string(), format!("pref len: {}", node.prefix.len()), format!("pos len: {}", node.positions.len())];
+			match options.labels {
+				GraphLabels::Sparseness => {
+					fields.push(format!("pref spars: {}", node.prefix.sparseness()));
+					fields.push(format!("pos spars: {}", node.positions.sparseness()));
+				},
+				GraphLabels::PrefixBits => fields.push(format!("pref: {}", bits_to_string(&node.prefix))),
+				GraphLabels::PrefixText => {
+					path.truncate(step.path_len);
+					if let Some((_, bit)) = step.parent {
+						path.push(bit);
+					}
+					path.extend((0..node.prefix.len()).map(|index| node.prefix.get(index)));
+					fields.push(format!("text: {}", bits_to_text(&path)));
+				}
+			}
+			let label: Vec<String> = fields.iter().map(|field| escape_label(field)).collect();
+			write!(out, " \"{}\" [label = \"{}\"", node_nr, label.join("|"))?;
+			if options.scale_by_size && self.len() > 0 {
+				let scale = (node.positions.len() as f64 / self.len() as f64).sqrt();
+				write!(out, ", width = {:.2}, height = {:.2}", 0.75 + 2.25 * scale, 0.5 + 1.5 * scale)?;
+			}
+			if is_highlighted {
+				out.write_all(b", style = \"filled\", fillcolor = \"gold\"")?;
+			}
+			out.write_all(b"];\n")?;
+
+			// the edge from the parent; the query only follows it to a node it matches
+			if let Some((parent_nr, _)) = step.parent {
+				if is_highlighted {
+					writeln!(out, " \"{}\" -> \"{}\" [color = \"red\", penwidth = 2]", parent_nr, node_nr)?;
+				} else {
+					writeln!(out, " \"{}\" -> \"{}\"", parent_nr, node_nr)?;
+				}
+			}
+
+			// the left child is written first, so it goes on the stack last
+			if let Some((left, right)) = node.children {
+				let path_len = path.len();
+				for &(bit, child) in &[(true, right), (false, left)] {
+					stack.push(Step {
+						id: child,
+						parent: Some((node_nr, bit)),
+						path_len,
+						query_offset: match next_query {
+							Some((query_bit, offset)) if query_bit == bit => Some(offset),
+							_ => None
+						}
+					});
+				}
+			}
+		}
+		out.write_all(b"}\n")
+	}
+}
+
+fn bits_to_text(bits: &[bool]) -> String {
+	let whole_bytes = bits.len() / 8;
+	let bytes: Vec<u8> = bits.chunks(8).map(|byte_bits| {
+		byte_bits.iter().enumerate().fold(0, |byte, (index, &bit)| if bit { byte | 1 << index } else { byte })
+	}).collect();
+	let mut text: String = String::from_utf8_lossy(&bytes[..whole_bytes]).escape_debug().collect();
+	if !bits.len().is_multiple_of(8) {
+		text.push('+');
+		text.extend(bits[whole_bytes * 8..].iter().map(|&bit| if bit { '1' } else { '0' }));
+	}
+	text
+}
+
+// characters with a meaning in a record label are escaped
+fn escape_label(text: &str) -> String {
+	let mut escaped = String::with_capacity(text.len());
+	for character in text.chars() {
+		match character {
+			'\\' | '"' | '{' | '}' | '<' | '>' | '|' => {
+				escaped.push('\\');
+				escaped.push(character);
+			},
+			_ => escaped.push(character)
+		}
+	}
+	escaped
+}
@@ -0,0 +1,235 @@
+// The structure of a trie as JSON, to inspect and compare the shapes of tries
+// in other tools. Every node has an id; the nodes are listed in level order
+// (breadth first, left before right), so the root has id 0. For example:
+//
+// { "length": 3, "nodes": [
+//   { "id": 0, "depth": 0, "prefix": "0", "positions_len": 3, "positions": "010", "left": 1, "right": 2 },
+//   { "id": 1, "depth": 1, "prefix": "", "positions_len": 2, "left": null, "right": null },
+//   ... ] }
+//
+// Bits are written as strings of '0' and '1', bit 0 first. A prefix that is
+// a whole number of bytes also has these bytes, and the text if they are UTF-8.
+// The positions of leaves are all 0, so only their number is written.
+
+extern crate dyn_bit_vec;
+extern crate serde_json;
+
+use self::dyn_bit_vec::DBVec;
+use std::cmp::Reverse;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use wavelet_trie::{WaveletTrie, Node};
+use wavelet_trie::positions::Positions;
+use wavelet_trie::file_format::{bits_to_bytes, check_children};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JsonStructure {
+	pub length: u64,            // the number of sequences in the trie
+	pub nodes: Vec<JsonNode>
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JsonNode {
+	pub id: u64,
+	pub depth: u64,                 // the root has depth 0
+	pub prefix: String,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub prefix_bytes: Option<Vec<u8>>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub prefix_text: Option<String>,
+	pub positions_len: u64,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub positions: Option<String>,  // only for nodes with children
+	pub left: Option<u64>,
+	pub right: Option<u64>
+}
+
+// reasons why JSON does not describe a valid trie
+#[derive(Debug)]
+pub enum JsonError {
+	Syntax(serde_json::Error),                          // not JSON, or not the expected fields
+	Invalid(&'static str),                              // the trie as a whole is not valid
+	InvalidNode { id: u64, reason: &'static str }       // a node is not valid
+}
+
+impl fmt::Display for JsonError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			JsonError::Syntax(ref err) => write!(f, "Invalid JSON: {}", err),
+			JsonError::Invalid(reason) => write!(f, "Invalid trie structure: {}", reason),
+			JsonError::InvalidNode { id, reason } => write!(f, "Invalid node {}: {}", id, reason)
+		}
+	}
+}
+
+impl Error for JsonError {}
+
+impl From<serde_json::Error> for JsonError {
+	fn from(err: serde_json::Error) -> Self {
+		JsonError::Syntax(err)
+	}
+}
+
+impl WaveletTrie {
+
+	// the structure of the trie as JSON, see the top of json.rs
+	pub fn to_json_structure(&self) -> String {
+		serde_json::to_string(&self.json_structure()).unwrap()
+	}
+
+	pub fn json_structure(&self) -> JsonStructure {
+		let mut nodes = Vec::new();
+		let mut queue = VecDeque::new();
+		queue.push_back((self.root_node(), 0));
+		// the id the next child gets; the nodes before it are in the queue or done
+		let mut next_id = 1;
+		while let Some((node, depth)) = queue.pop_front() {
+			let id = nodes.len() as u64;
+			let (left, right, positions) = match node.children {
+				Some((left, right)) => {
+					queue.push_back((self.node(left), depth + 1));
+					queue.push_back((self.node(right), depth + 1));
+					next_id += 2;
+					(Some(next_id - 2), Some(next_id - 1), Some(bits_to_string(&node.positions.bits())))
+				},
+				None => (None, None, None)
+			};
+			let prefix_bytes = if node.prefix.len() % 8 == 0 && !node.prefix.is_empty() {
+				Some(bits_to_bytes(&node.prefix))
+			} else {
+				None
+			};
+			let prefix_text = prefix_bytes.as_ref().and_then(|bytes| String::from_utf8(bytes.clone()).ok());
+			nodes.push(JsonNode {
+				id,
+				depth,
+				prefix: bits_to_string(&node.prefix),
+				prefix_bytes,
+				prefix_text,
+				positions_len: node.positions.len(),
+				positions,
+				left,
+				right
+			});
+		}
+		JsonStructure {
+			length: self.len(),
+			nodes
+		}
+	}
+
+	// rebuilds a trie from the JSON made by to_json_structure, and checks
+	// that it is a valid trie. The nodes can be in any order.
+	pub fn from_json_structure(json: &str) -> Result<Self, JsonError> {
+		let structure: JsonStructure = serde_json::from_str(json)?;
+		WaveletTrie::from_json(&structure)
+	}
+
+	pub fn from_json(structure: &JsonStructure) -> Result<Self, JsonError> {
+		let nr_nodes = structure.nodes.len() as u64;
+		if nr_nodes == 0 {
+			return Err(JsonError::Invalid("there are no nodes"));
+		}
+
+		// the nodes by id, and how often they are a child
+		let mut by_id: Vec<Option<&JsonNode>> = vec![None; nr_nodes as usize];
+		for node in &structure.nodes {
+			if node.id >= nr_nodes {
+				return Err(JsonError::InvalidNode { id: node.id, reason: "the ids must be 0 up to the number of nodes" });
+			}
+			if by_id[node.id as usize].replace(node).is_some() {
+				return Err(JsonError::InvalidNode { id: node.id, reason: "the id is used twice" });
+			}
+		}
+		let mut is_child = vec![false; nr_nodes as usize];
+		for node in &structure.nodes {
+			let invalid = |reason| Err(JsonError::InvalidNode { id: node.id, reason });
+			match (node.left, node.right) {
+				(Some(left), Some(right)) => {
+					for &child in &[left, right] {
+						if child == 0 || child >= nr_nodes {
+							return invalid("a child id does not exist, or is the root");
+						}
+						if is_child[child as usize] {
+							return invalid("a child has two parents");
+						}
+						is_child[child as usize] = true;
+						if by_id[child as usize].unwrap().depth != node.depth + 1 {
+							return invalid("the depth of a child is not one more");
+						}
+					}
+				},
+				(None, None) => {},
+				_ => return invalid("a node must have both children or none")
+			}
+		}
+		if by_id[0].unwrap().depth != 0 {
+			return Err(JsonError::InvalidNode { id: 0, reason: "the root must have depth 0" });
+		}
+		// every node but the root is a child once, and depths grow: this is a tree
+		if is_child.iter().filter(|&&child| child).count() as u64 != nr_nodes - 1 {
+			return Err(JsonError::Invalid("not all nodes are part of the trie"));
+		}
+
+		// build the nodes from the deepest up; the ids are the indices in the arena
+		let mut order: Vec<&JsonNode> = structure.nodes.iter().collect();
+		order.sort_by_key(|node| Reverse(node.depth));
+		let mut built: Vec<Option<Node>> = (0..nr_nodes).map(|_| None).collect();
+		for node in order {
+			let invalid = |reason| JsonError::InvalidNode { id: node.id, reason };
+			let prefix = string_to_bits(&node.prefix).ok_or_else(|| invalid("bits must be '0' or '1'"))?;
+			if let Some(ref bytes) = node.prefix_bytes {
+				if prefix.len() % 8 != 0 || *bytes != bits_to_bytes(&prefix) {
+					return Err(invalid("prefix_bytes does not match prefix"));
+				}
+			}
+			if let Some(ref text) = node.prefix_text {
+				if prefix.len() % 8 != 0 || *text.as_bytes() != *bits_to_bytes(&prefix) {
+					return Err(invalid("prefix_text does not match prefix"));
+				}
+			}
+			let mut trie_node = Node::leaf(prefix, Positions::from_elem(node.positions_len, false));
+			match (node.left, node.right, &node.positions) {
+				(Some(left), Some(right), Some(positions)) => {
+					let positions = string_to_bits(positions).ok_or_else(|| invalid("bits must be '0' or '1'"))?;
+					if positions.len() != node.positions_len {
+						return Err(invalid("positions_len does not match positions"));
+					}
+					check_children(&positions, built[left as usize].as_ref().unwrap(), built[right as usize].as_ref().unwrap())
+						.map_err(|_| invalid("the positions do not match the children"))?;
+					trie_node.positions = Positions::from_dbvec(positions);
+					trie_node.children = Some((left as u32, right as u32));
+				},
+				(None, None, None) => {},
+				(None, None, Some(_)) => return Err(invalid("a leaf has no positions, only positions_len")),
+				_ => return Err(invalid("a node with children needs positions"))
+			}
+			built[node.id as usize] = Some(trie_node);
+		}
+		let trie = WaveletTrie::from_nodes(built.into_iter().map(Option::unwrap).collect(), 0);
+		if trie.len() != structure.length {
+			return Err(JsonError::Invalid("length does not match the number of sequences in the root"));
+		}
+		if trie.len() == 0 && !trie.root_node().prefix.is_empty() {
+			return Err(JsonError::InvalidNode { id: 0, reason: "an empty trie has no prefix" });
+		}
+		Ok(trie)
+	}
+}
+
+pub(crate) fn bits_to_string(bits: &DBVec) -> String {
+	(0..bits.len()).map(|index| if bits.get(index) { '1' } else { '0' }).collect()
+}
+
+fn string_to_bits(text: &str) -> Option<DBVec> {
+	let mut bits = DBVec::new();
+	for character in text.chars() {
+		match character {
+			'0' => bits.push(false),
+			'1' => bits.push(true),
+			_ => return None
+		}
+	}
+	Some(bits)
+}
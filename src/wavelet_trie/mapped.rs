@@ -0,0 +1,754 @@
+// The flat payload encoding (2) of the file format, and a wavelet trie that
+// answers queries directly on a memory-mapped file in that encoding.
+//
+// The payload consists of little endian 64-bit words; since the header is 40
+// bytes long, every word is 8-byte aligned in the file.
+//
+// word    field
+//    0    number of nodes (n)
+//    1    total number of prefix bits (P)
+//    2    total number of position bits (Q)
+//    3    number of select samples for 1s (s1)
+//    4    number of select samples for 0s (s0)
+//  5-7    reserved, 0
+//    8    node table: n records of 6 words, the nodes in level order:
+//           bit offset of the prefix in the prefix area, prefix length,
+//           bit offset of the positions in the position area, number of positions,
+//           node number of the left child, node number of the right child.
+//         Leaves have no positions in the position area (they are all 0) and
+//         u64::MAX as children.
+//  ...    prefix area: the prefixes of all nodes, ⌈P/64⌉ words
+//  ...    position area: the positions of all nodes with children, ⌈Q/64⌉ words
+//  ...    rank directory: the number of 1s in the position area before every
+//         block of 512 bits, and the total number of 1s at the end; ⌈Q/512⌉ + 1 words
+//  ...    select samples for 1s: for the (k * 4096 + 1)-th 1 in the position area,
+//         the number of the 512-bit block that holds it; s1 words
+//  ...    select samples for 0s: the same for 0s; s0 words
+//
+// Bit i of an area is bit i % 64 of word i / 64.
+
+extern crate dyn_bit_vec;
+extern crate memmap;
+extern crate crc32fast;
+
+use self::dyn_bit_vec::DBVec;
+use self::memmap::Mmap;
+use self::crc32fast::Hasher;
+use std::cmp;
+use std::fs::File;
+use std::path::Path;
+use wavelet_trie::{WaveletTrie, Node, DecodeError, push_node};
+use wavelet_trie::positions::{Positions, SELECTS_PER_PASS};
+use wavelet_trie::file_format::{FormatError, BitWriter, ENCODING_FLAT, HEADER_LEN, decode_header, bytes_to_u32, bytes_to_u64, u64_to_bytes};
+
+const NO_CHILD: u64 = u64::MAX;
+const PAYLOAD_HEADER_WORDS: u64 = 8;
+const NODE_WORDS: u64 = 6;
+const BLOCK_BITS: u64 = 512;
+const WORDS_PER_BLOCK: u64 = BLOCK_BITS / 64;
+const SELECT_SAMPLE_RATE: u64 = 4096;
+
+impl WaveletTrie {
+
+	// returns the flat payload and the number of nodes
+	pub(crate) fn encode_flat(&self) -> (Vec<u8>, u64) {
+		let nodes = self.level_order();
+		let mut prefixes = BitWriter::new();
+		let mut positions = BitWriter::new();
+		let mut node_table = Vec::with_capacity(nodes.len() * NODE_WORDS as usize);
+		let mut next_child = 1;
+		for node in &nodes {
+			node_table.push(prefixes.len());
+			node_table.push(node.prefix.len());
+			prefixes.push_bits(&node.prefix);
+			if node.is_leaf() {
+				node_table.extend_from_slice(&[0, node.positions.len(), NO_CHILD, NO_CHILD]);
+			} else {
+				node_table.extend_from_slice(&[positions.len(), node.positions.len(), next_child, next_child + 1]);
+				positions.push_bits(&node.positions.bits());
+				next_child += 2;
+			}
+		}
+		let prefix_bits = prefixes.len();
+		let position_bits = positions.len();
+		let prefix_words = bytes_to_words(&prefixes.into_bytes());
+		let position_words = bytes_to_words(&positions.into_bytes());
+
+		// rank directory
+		let mut rank_directory = Vec::with_capacity(position_words.len() / WORDS_PER_BLOCK as usize + 2);
+		let mut ones = 0;
+		for (word_nr, word) in position_words.iter().enumerate() {
+			if (word_nr as u64).is_multiple_of(WORDS_PER_BLOCK) {
+				rank_directory.push(ones);
+			}
+			ones += word.count_ones() as u64;
+		}
+		if rank_directory.len() as u64 != position_bits.div_ceil(BLOCK_BITS) {
+			rank_directory.push(ones);
+		}
+		rank_directory.push(ones);
+		let zeros = position_bits - ones;
+
+		// select samples
+		let nr_blocks = rank_directory.len() - 1;
+		let mut one_samples = Vec::new();
+		let mut zero_samples = Vec::new();
+		for block_nr in 0..nr_blocks {
+			let ones_before = rank_directory[block_nr];
+			let ones_after = rank_directory[block_nr + 1];
+			while one_samples.len() as u64 * SELECT_SAMPLE_RATE < ones_after {
+				one_samples.push(block_nr as u64);
+			}
+			let block_end = ((block_nr as u64 + 1) * BLOCK_BITS).min(position_bits);
+			let zeros_after = block_end - ones_after;
+			while zero_samples.len() as u64 * SELECT_SAMPLE_RATE < zeros_after {
+				zero_samples.push(block_nr as u64);
+			}
+			debug_assert!(ones_before <= ones_after);
+		}
+		debug_assert!(one_samples.len() as u64 == ones.div_ceil(SELECT_SAMPLE_RATE));
+		debug_assert!(zero_samples.len() as u64 == zeros.div_ceil(SELECT_SAMPLE_RATE));
+
+		let mut words = vec![nodes.len() as u64, prefix_bits, position_bits, one_samples.len() as u64, zero_samples.len() as u64, 0, 0, 0];
+		for section in &[node_table, prefix_words, position_words, rank_directory, one_samples, zero_samples] {
+			words.extend_from_slice(section);
+		}
+		let mut payload = Vec::with_capacity(words.len() * 8);
+		for word in words {
+			payload.extend_from_slice(&u64_to_bytes(word));
+		}
+		(payload, nodes.len() as u64)
+	}
+}
+
+// little endian bytes to words; the last word is padded with 0s
+fn bytes_to_words(bytes: &[u8]) -> Vec<u64> {
+	bytes.chunks(8).map(|chunk| {
+		let mut word = [0u8; 8];
+		word[..chunk.len()].copy_from_slice(chunk);
+		bytes_to_u64(&word)
+	}).collect()
+}
+
+// a node record from the node table
+#[derive(Clone, Copy, Debug)]
+struct FlatNode {
+	prefix_start: u64,
+	prefix_len: u64,
+	positions_start: u64,
+	positions_len: u64,
+	left: u64,
+	right: u64
+}
+
+impl FlatNode {
+	fn is_leaf(&self) -> bool {
+		self.left == NO_CHILD
+	}
+
+	fn child(&self, bit: bool) -> u64 {
+		if bit { self.right } else { self.left }
+	}
+}
+
+// where the sections of a flat payload start, in words
+#[derive(Clone, Copy, Debug)]
+struct FlatLayout {
+	nr_nodes: u64,
+	prefix_bits: u64,
+	position_bits: u64,
+	nr_one_samples: u64,
+	nr_zero_samples: u64,
+	node_table: u64,
+	prefixes: u64,
+	positions: u64,
+	rank_directory: u64,
+	one_samples: u64,
+	zero_samples: u64
+}
+
+// a wavelet trie in the flat encoding, on top of the bytes of the payload.
+// all queries give the same answers as on the WaveletTrie that was saved.
+pub(crate) struct FlatView<'a> {
+	bytes: &'a [u8],
+	layout: FlatLayout
+}
+
+impl<'a> FlatView<'a> {
+
+	// checks the layout, the rank directory, the select samples and the node table,
+	// so queries on the view cannot go out of bounds or loop forever. The bits
+	// themselves are not checked.
+	pub fn new(bytes: &'a [u8]) -> Result<Self, FormatError> {
+		let layout = FlatView::parse_layout(bytes)?;
+		let view = FlatView {
+			bytes,
+			layout
+		};
+		view.check_rank_directory()?;
+		view.check_nodes()?;
+		Ok(view)
+	}
+
+	fn parse_layout(bytes: &[u8]) -> Result<FlatLayout, FormatError> {
+		let too_short = FormatError::Corrupt("the flat payload is too short");
+		if !bytes.len().is_multiple_of(8) || (bytes.len() as u64) < PAYLOAD_HEADER_WORDS * 8 {
+			return Err(too_short);
+		}
+		let word = |word_nr: u64| bytes_to_u64(&bytes[(word_nr * 8) as usize..]);
+		let nr_words = bytes.len() as u64 / 8;
+		let (nr_nodes, prefix_bits, position_bits, nr_one_samples, nr_zero_samples) = (word(0), word(1), word(2), word(3), word(4));
+		if nr_nodes == 0 || nr_nodes > nr_words || prefix_bits / 64 > nr_words || position_bits / 64 > nr_words
+			|| nr_one_samples > nr_words || nr_zero_samples > nr_words {
+			return Err(too_short);
+		}
+		let node_table = PAYLOAD_HEADER_WORDS;
+		let prefixes = node_table + nr_nodes * NODE_WORDS;
+		let positions = prefixes + prefix_bits.div_ceil(64);
+		let rank_directory = positions + position_bits.div_ceil(64);
+		let one_samples = rank_directory + position_bits.div_ceil(BLOCK_BITS) + 1;
+		let zero_samples = one_samples + nr_one_samples;
+		if zero_samples + nr_zero_samples != nr_words {
+			return Err(FormatError::Corrupt("the flat payload does not have the size of its sections"));
+		}
+		Ok(FlatLayout {
+			nr_nodes,
+			prefix_bits,
+			position_bits,
+			nr_one_samples,
+			nr_zero_samples,
+			node_table,
+			prefixes,
+			positions,
+			rank_directory,
+			one_samples,
+			zero_samples
+		})
+	}
+
+	// the directory starts at 0 and every block adds at most its number of bits, so
+	// the counts of 1s and 0s before a block never exceed the bits before it. Every
+	// select sample names the block that holds its 1 or 0 according to the directory.
+	fn check_rank_directory(&self) -> Result<(), FormatError> {
+		let nr_blocks = self.nr_blocks();
+		if self.word(self.layout.rank_directory) != 0 {
+			return Err(FormatError::Corrupt("the rank directory does not match the positions"));
+		}
+		for block_nr in 0..nr_blocks {
+			let block_len = cmp::min(BLOCK_BITS, self.layout.position_bits - block_nr * BLOCK_BITS);
+			let ones_before = self.word(self.layout.rank_directory + block_nr);
+			let ones_after = self.word(self.layout.rank_directory + block_nr + 1);
+			if ones_after < ones_before || ones_after - ones_before > block_len {
+				return Err(FormatError::Corrupt("the rank directory does not match the positions"));
+			}
+		}
+		for &(bit, samples, nr_samples) in &[(true, self.layout.one_samples, self.layout.nr_one_samples),
+				(false, self.layout.zero_samples, self.layout.nr_zero_samples)] {
+			let mut previous_block_nr = 0;
+			for sample_nr in 0..nr_samples {
+				let block_nr = self.word(samples + sample_nr);
+				let nr = sample_nr * SELECT_SAMPLE_RATE + 1;
+				if block_nr < previous_block_nr || block_nr >= nr_blocks
+					|| self.count_before(bit, block_nr) >= nr || self.count_before(bit, block_nr + 1) < nr {
+					return Err(FormatError::Corrupt("the select samples do not match the rank directory"));
+				}
+				previous_block_nr = block_nr;
+			}
+		}
+		Ok(())
+	}
+
+	fn check_nodes(&self) -> Result<(), FormatError> {
+		let total_ones = self.word(self.layout.rank_directory + self.layout.position_bits.div_ceil(BLOCK_BITS));
+		if total_ones > self.layout.position_bits
+			|| self.layout.nr_one_samples != total_ones.div_ceil(SELECT_SAMPLE_RATE)
+			|| self.layout.nr_zero_samples != (self.layout.position_bits - total_ones).div_ceil(SELECT_SAMPLE_RATE) {
+			return Err(FormatError::Corrupt("the rank directory does not match the select samples"));
+		}
+		let mut is_child = vec![false; self.layout.nr_nodes as usize];
+		for node_nr in 0..self.layout.nr_nodes {
+			let node = self.node(node_nr);
+			if node.prefix_start > self.layout.prefix_bits || node.prefix_len > self.layout.prefix_bits - node.prefix_start {
+				return Err(FormatError::Corrupt("a prefix lies outside the prefix area"));
+			}
+			if node.is_leaf() {
+				if node.right != NO_CHILD {
+					return Err(FormatError::Corrupt("a node has only one child"));
+				}
+				continue;
+			}
+			if node.positions_start > self.layout.position_bits || node.positions_len > self.layout.position_bits - node.positions_start {
+				return Err(FormatError::Corrupt("positions lie outside the position area"));
+			}
+			// children come after their parent, and have exactly one parent
+			for &child_nr in &[node.left, node.right] {
+				if child_nr <= node_nr || child_nr >= self.layout.nr_nodes || is_child[child_nr as usize] {
+					return Err(FormatError::Corrupt("the node table is not a tree"));
+				}
+				is_child[child_nr as usize] = true;
+			}
+			// the directory is not checked against the bits, so the counts can be off
+			let mismatch = FormatError::Corrupt("the positions of a node do not match its children");
+			let ones = match self.rank_ones(node.positions_start + node.positions_len).checked_sub(self.rank_ones(node.positions_start)) {
+				Some(ones) if ones <= node.positions_len => ones,
+				_ => return Err(mismatch)
+			};
+			let (left, right) = (self.node(node.left), self.node(node.right));
+			if node.positions_len - ones != left.positions_len || ones != right.positions_len || ones == 0 || ones == node.positions_len {
+				return Err(mismatch);
+			}
+		}
+		if is_child.iter().skip(1).any(|is_child| !is_child) {
+			return Err(FormatError::Corrupt("the node table is not a tree"));
+		}
+		Ok(())
+	}
+
+	pub fn nr_nodes(&self) -> u64 {
+		self.layout.nr_nodes
+	}
+
+	pub fn len(&self) -> u64 {
+		self.node(0).positions_len
+	}
+
+	// retrieves the sequence at the given index
+	pub fn access(&self, index: u64) -> DBVec {
+		let mut result = DBVec::new();
+		let mut node = self.node(0);
+		let mut index = index;
+		loop {
+			for bit_nr in 0..node.prefix_len {
+				result.push(self.bit(self.layout.prefixes, node.prefix_start + bit_nr));
+			}
+			if node.is_leaf() {
+				return result;
+			}
+			let bit = self.bit(self.layout.positions, node.positions_start + index);
+			index = self.node_rank(&node, bit, index);
+			result.push(bit);
+			node = self.node(node.child(bit));
+		}
+	}
+
+	// counts the number of occurrences of "sequence" (can be a prefix) up to index − 1.
+	// returns None if sequence does not occur
+	pub fn rank(&self, sequence: &DBVec, index: u64) -> Option<u64> {
+		let mut node = self.node(0);
+		if node.prefix_len == 0 && node.positions_len == 0 {
+			return None;
+		}
+		let mut index = index;
+		let mut offset = 0;
+		loop {
+			let remaining = sequence.len() - offset;
+			if remaining <= node.prefix_len {
+				return if self.prefix_matches(&node, sequence, offset, remaining) { Some(index) } else { None };
+			}
+			if !self.prefix_matches(&node, sequence, offset, node.prefix_len) {
+				return None;
+			}
+			let bit = sequence.get(offset + node.prefix_len);
+			index = self.node_rank(&node, bit, index);
+			if node.is_leaf() {
+				// same answer as WaveletTrie::rank
+				return Some(index);
+			}
+			offset += node.prefix_len + 1;
+			node = self.node(node.child(bit));
+		}
+	}
+
+	// find the position of the occurrence_nr-th given sequence (can be a prefix)
+	// an occurrence number starts at 1. returns None if not found.
+	pub fn select(&self, sequence: &DBVec, occurrence_nr: u64) -> Option<u64> {
+		if occurrence_nr == 0 {
+			return None;
+		}
+		let path = match self.find(sequence) {
+			Some((path, _)) => path,
+			None => return None
+		};
+		let mut position = occurrence_nr - 1;
+		for &(ref node, bit) in path.iter().rev() {
+			position = self.node_select(node, bit, position + 1)?;
+		}
+		Some(position)
+	}
+
+	// find the positions of all occurrences of the given sequence (can be prefix)
+	pub fn select_all(&self, sequence: &DBVec) -> Vec<u64> {
+		let (path, found) = match self.find(sequence) {
+			Some(found) => found,
+			None => return Vec::new()
+		};
+		// the positions stay sorted, so every level maps them all in one pass, like WaveletTrie::select_all
+		let mut result: Vec<u64> = (0..found.positions_len).collect();
+		for &(ref node, bit) in path.iter().rev() {
+			if !self.node_select_sorted(node, bit, &mut result) {
+				// only if the bits do not match the rank directory
+				return Vec::new();
+			}
+		}
+		result
+	}
+
+	// descends to the node where "sequence" ends. returns the nodes passed on the
+	// way with the branch taken, and the node itself; None if the sequence is not in the trie.
+	fn find(&self, sequence: &DBVec) -> Option<(Vec<(FlatNode, bool)>, FlatNode)> {
+		let mut path = Vec::new();
+		let mut node = self.node(0);
+		let mut offset = 0;
+		loop {
+			let remaining = sequence.len() - offset;
+			if remaining <= node.prefix_len {
+				return if self.prefix_matches(&node, sequence, offset, remaining) { Some((path, node)) } else { None };
+			}
+			if node.is_leaf() || !self.prefix_matches(&node, sequence, offset, node.prefix_len) {
+				return None;
+			}
+			let bit = sequence.get(offset + node.prefix_len);
+			path.push((node, bit));
+			offset += node.prefix_len + 1;
+			node = self.node(node.child(bit));
+		}
+	}
+
+	// rebuilds the dynamic trie; the nodes keep their numbers as indices in the arena
+	pub fn to_trie(&self) -> WaveletTrie {
+		let mut nodes = Vec::with_capacity(self.layout.nr_nodes as usize);
+		for node_nr in 0..self.layout.nr_nodes {
+			let node = self.node(node_nr);
+			let mut prefix = DBVec::new();
+			for bit_nr in 0..node.prefix_len {
+				prefix.push(self.bit(self.layout.prefixes, node.prefix_start + bit_nr));
+			}
+			if node.is_leaf() {
+				push_node(&mut nodes, Node::leaf(prefix, Positions::from_elem(node.positions_len, false)));
+			} else {
+				let mut positions = DBVec::new();
+				for bit_nr in 0..node.positions_len {
+					positions.push(self.bit(self.layout.positions, node.positions_start + bit_nr));
+				}
+				push_node(&mut nodes, Node {
+					prefix,
+					positions: Positions::from_dbvec(positions),
+					children: Some((node.left as u32, node.right as u32))
+				});
+			}
+		}
+		WaveletTrie::from_nodes(nodes, 0)
+	}
+
+	fn word(&self, word_nr: u64) -> u64 {
+		bytes_to_u64(&self.bytes[(word_nr * 8) as usize..])
+	}
+
+	fn bit(&self, area: u64, bit_nr: u64) -> bool {
+		(self.word(area + bit_nr / 64) >> (bit_nr % 64)) & 1 == 1
+	}
+
+	fn node(&self, node_nr: u64) -> FlatNode {
+		let start = self.layout.node_table + node_nr * NODE_WORDS;
+		FlatNode {
+			prefix_start: self.word(start),
+			prefix_len: self.word(start + 1),
+			positions_start: self.word(start + 2),
+			positions_len: self.word(start + 3),
+			left: self.word(start + 4),
+			right: self.word(start + 5)
+		}
+	}
+
+	// true if the first "len" bits of the prefix of node equal the bits of sequence from offset on
+	fn prefix_matches(&self, node: &FlatNode, sequence: &DBVec, offset: u64, len: u64) -> bool {
+		(0..len).all(|bit_nr| self.bit(self.layout.prefixes, node.prefix_start + bit_nr) == sequence.get(offset + bit_nr))
+	}
+
+	// the number of 1s in the position area before position "pos"
+	fn rank_ones(&self, pos: u64) -> u64 {
+		let block_nr = pos / BLOCK_BITS;
+		let mut ones = self.word(self.layout.rank_directory + block_nr);
+		for word_nr in block_nr * WORDS_PER_BLOCK..pos / 64 {
+			ones += self.word(self.layout.positions + word_nr).count_ones() as u64;
+		}
+		if !pos.is_multiple_of(64) {
+			let mask = (1u64 << (pos % 64)) - 1;
+			ones += (self.word(self.layout.positions + pos / 64) & mask).count_ones() as u64;
+		}
+		ones
+	}
+
+	fn nr_blocks(&self) -> u64 {
+		self.layout.position_bits.div_ceil(BLOCK_BITS)
+	}
+
+	// the number of 1s (or 0s) in the position area before block "block_nr", from the rank directory
+	fn count_before(&self, bit: bool, block_nr: u64) -> u64 {
+		let ones = self.word(self.layout.rank_directory + block_nr);
+		if bit { ones } else { (block_nr * BLOCK_BITS).min(self.layout.position_bits) - ones }
+	}
+
+	// the position of the nr-th 1 (or 0) in the position area; it must exist.
+	// If the bits do not match the directory, the answer is wrong but within the area.
+	fn select_area(&self, bit: bool, nr: u64) -> u64 {
+		let count_before = |block_nr: u64| self.count_before(bit, block_nr);
+		// the samples narrow down the blocks; a binary search finds the block
+		let (samples, nr_samples) = if bit {
+			(self.layout.one_samples, self.layout.nr_one_samples)
+		} else {
+			(self.layout.zero_samples, self.layout.nr_zero_samples)
+		};
+		let sample_nr = (nr - 1) / SELECT_SAMPLE_RATE;
+		if sample_nr >= nr_samples {
+			return self.layout.position_bits;
+		}
+		let mut low = self.word(samples + sample_nr);
+		let mut high = if sample_nr + 1 < nr_samples {
+			self.word(samples + sample_nr + 1)
+		} else {
+			self.nr_blocks() - 1
+		};
+		while low < high {
+			let middle = (low + high).div_ceil(2);
+			if count_before(middle) < nr {
+				low = middle;
+			} else {
+				high = middle - 1;
+			}
+		}
+
+		// scan the words of the block
+		let mut left = nr - count_before(low);
+		let mut word_nr = low * WORDS_PER_BLOCK;
+		let nr_words = self.layout.position_bits.div_ceil(64);
+		loop {
+			if word_nr >= nr_words {
+				return self.layout.position_bits;
+			}
+			let word = self.word(self.layout.positions + word_nr);
+			let word = if bit { word } else { !word };
+			let count = word.count_ones() as u64;
+			if count >= left {
+				let mut word = word;
+				for _ in 1..left {
+					word &= word - 1;
+				}
+				return word_nr * 64 + word.trailing_zeros() as u64;
+			}
+			left -= count;
+			word_nr += 1;
+		}
+	}
+
+	// rank within the positions of a node
+	fn node_rank(&self, node: &FlatNode, bit: bool, index: u64) -> u64 {
+		if node.is_leaf() {
+			// the positions of a leaf are all 0
+			return if bit { 0 } else { index };
+		}
+		let ones = self.rank_ones(node.positions_start + index).saturating_sub(self.rank_ones(node.positions_start));
+		if bit { ones } else { index.saturating_sub(ones) }
+	}
+
+	// select within the positions of a node
+	fn node_select(&self, node: &FlatNode, bit: bool, nr: u64) -> Option<u64> {
+		if nr == 0 {
+			return None;
+		}
+		if node.is_leaf() {
+			return if !bit && nr <= node.positions_len { Some(nr - 1) } else { None };
+		}
+		let ones_before = self.rank_ones(node.positions_start);
+		let ones = self.rank_ones(node.positions_start + node.positions_len).saturating_sub(ones_before);
+		let (before, count) = if bit {
+			(ones_before, ones)
+		} else {
+			(node.positions_start.saturating_sub(ones_before), node.positions_len.saturating_sub(ones))
+		};
+		if nr > count {
+			None
+		} else {
+			Some(self.select_area(bit, before + nr).saturating_sub(node.positions_start))
+		}
+	}
+
+	// replaces the sorted occurrence numbers (from 0) of "bit" in the positions of an
+	// inner node by their indices, like Positions::select_sorted: in one pass over the
+	// words of the node if there are many. False if the bits do not match the rank directory
+	fn node_select_sorted(&self, node: &FlatNode, bit: bool, nrs: &mut [u64]) -> bool {
+		if (nrs.len() as u64) * SELECTS_PER_PASS < node.positions_len {
+			for nr in nrs.iter_mut() {
+				*nr = match self.node_select(node, bit, *nr + 1) {
+					Some(position) => position,
+					None => return false
+				};
+			}
+			return true;
+		}
+		let (start, end) = (node.positions_start, node.positions_start + node.positions_len);
+		let mut next = 0;   // the first number that is not found yet
+		let mut seen = 0;   // the number of "bit" in the node before the current word
+		let mut word_nr = start / 64;
+		while next < nrs.len() && word_nr * 64 < end {
+			let word = self.word(self.layout.positions + word_nr);
+			let mut word = if bit { word } else { !word };
+			// only the bits of the node count
+			if word_nr * 64 < start {
+				word &= !0u64 << (start % 64);
+			}
+			if end < (word_nr + 1) * 64 {
+				word &= (1u64 << (end % 64)) - 1;
+			}
+			let count = word.count_ones() as u64;
+			while next < nrs.len() && nrs[next] < seen + count {
+				let mut nth = word;
+				for _ in 0..nrs[next] - seen {
+					nth &= nth - 1;
+				}
+				nrs[next] = word_nr * 64 + nth.trailing_zeros() as u64 - start;
+				next += 1;
+			}
+			seen += count;
+			word_nr += 1;
+		}
+		next == nrs.len()
+	}
+}
+
+/// A read-only wavelet trie that answers queries directly on a memory-mapped
+/// file, written with `WaveletTrie::save_with(writer, Encoding::Flat)`.
+///
+/// Opening the file checks its header, node table and directories once; the
+/// bits are read from the mapping when a query needs them. So opening is fast
+/// and several processes on the same machine share one copy of the index in the
+/// page cache. Use `verify` to check the checksum of the whole file.
+///
+/// The file must not be changed while it is open: a truncated file makes queries
+/// crash with SIGBUS, and other changes are undefined behaviour.
+///
+/// # Examples
+///
+/// ```rust
+/// extern crate wavelet_trie;
+///
+/// use std::fs::File;
+/// use wavelet_trie::wavelet_trie::WaveletTrie;
+/// use wavelet_trie::wavelet_trie::file_format::Encoding;
+/// use wavelet_trie::wavelet_trie::mapped::MappedWaveletTrie;
+///
+/// let mut wt = WaveletTrie::new();
+/// wt.append_str("Hello world!").unwrap();
+/// wt.append_str("Hello everybody!").unwrap();
+///
+/// let path = std::env::temp_dir().join("wavelet_trie_mapped_doc_example.wt");
+/// wt.save_with(&mut File::create(&path).unwrap(), Encoding::Flat).unwrap();
+///
+/// let mapped = MappedWaveletTrie::open(&path).unwrap();
+/// assert_eq!("Hello everybody!", mapped.access_str(1).unwrap());
+/// assert_eq!(vec![0, 1], mapped.select_all_str_prefix("Hell"));
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+pub struct MappedWaveletTrie {
+	map: Mmap,
+	payload_len: usize,
+	layout: FlatLayout    // checked when the file was opened
+}
+
+impl MappedWaveletTrie {
+
+	pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, FormatError> {
+		let file = File::open(path)?;
+		// SAFETY: the mapping stays valid as long as nobody changes the file. If another
+		// process truncates it, reading the lost pages raises SIGBUS; if it writes to it,
+		// the bytes change under the checked layout, which is undefined behaviour. Callers
+		// must not change a file while it is open as a MappedWaveletTrie.
+		let map = unsafe { Mmap::map(&file)? };
+		if map.len() < HEADER_LEN + 4 {
+			return Err(FormatError::Corrupt("the file is too short"));
+		}
+		let mut header = [0u8; HEADER_LEN];
+		header.copy_from_slice(&map[..HEADER_LEN]);
+		let header_info = decode_header(&header)?;
+		if header_info.encoding != ENCODING_FLAT {
+			// only the flat encoding can be queried in place
+			return Err(FormatError::UnsupportedFlags(header_info.encoding));
+		}
+		if header_info.payload_len != (map.len() - HEADER_LEN - 4) as u64 {
+			return Err(FormatError::Corrupt("the payload length does not match the file size"));
+		}
+		let payload_len = header_info.payload_len as usize;
+		let layout = {
+			let view = FlatView::new(&map[HEADER_LEN..HEADER_LEN + payload_len])?;
+			if view.nr_nodes() != header_info.node_count || view.len() != header_info.length {
+				return Err(FormatError::Corrupt("the payload does not match the header"));
+			}
+			view.layout
+		};
+		Ok(MappedWaveletTrie {
+			map,
+			payload_len,
+			layout
+		})
+	}
+
+	// checks the checksum of the whole file; this reads every byte
+	pub fn verify(&self) -> Result<(), FormatError> {
+		let checked_len = HEADER_LEN + self.payload_len;
+		let mut hasher = Hasher::new();
+		hasher.update(&self.map[..checked_len]);
+		let found = hasher.finalize();
+		let expected = bytes_to_u32(&self.map[checked_len..]);
+		if expected != found {
+			return Err(FormatError::ChecksumMismatch { expected, found });
+		}
+		Ok(())
+	}
+
+	// the view on the payload, with the layout that was checked when the file was opened
+	fn view<'a>(&'a self) -> FlatView<'a> {
+		FlatView {
+			bytes: &self.map[HEADER_LEN..HEADER_LEN + self.payload_len],
+			layout: self.layout
+		}
+	}
+
+	pub fn len(&self) -> u64 {
+		self.view().len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	pub fn access(&self, index: u64) -> DBVec {
+		self.view().access(index)
+	}
+
+	pub fn rank(&self, sequence: &DBVec, index: u64) -> Option<u64> {
+		self.view().rank(sequence, index)
+	}
+
+	pub fn select(&self, sequence: &DBVec, occurrence_nr: u64) -> Option<u64> {
+		self.view().select(sequence, occurrence_nr)
+	}
+
+	pub fn select_all(&self, sequence: &DBVec) -> Vec<u64> {
+		self.view().select_all(sequence)
+	}
+
+	// retrieves the string at the given index, see WaveletTrie::access_str
+	pub fn access_str(&self, index: u64) -> Result<String, DecodeError> {
+		WaveletTrie::bitvec_to_text(&self.access(index))
+	}
+
+	// finds the positions of all strings starting with "text"
+	pub fn select_all_str_prefix(&self, text: &str) -> Vec<u64> {
+		self.select_all(&WaveletTrie::prefix_to_bitvec(text))
+	}
+
+	// finds the positions of all occurrences of exactly "text"
+	pub fn select_all_str_exact(&self, text: &str) -> Vec<u64> {
+		self.select_all(&WaveletTrie::text_to_bitvec(text))
+	}
+}
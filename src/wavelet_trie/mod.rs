@@ -3,11 +3,39 @@ extern crate serde;
 extern crate bincode;
 
 use self::dyn_bit_vec::DBVec;
+use std::cmp;
 use std::fmt;
+use std::mem;
 use std::vec::Vec;
 use std::string::FromUtf8Error;
 use std::io::{Read, Write};
+use std::error::Error;
 use self::bincode::{serialize_into, deserialize_from};
+use self::positions::{Positions, shrink_bits};
+use self::builder::build_nodes;
+
+// the byte appended to every string by append_str
+const TERMINATOR: u8 = 0b0;
+
+// reasons why a sequence in the trie cannot be turned back into a string
+#[derive(Debug, PartialEq)]
+pub enum DecodeError {
+	MissingTerminator,           // the sequence does not end with the terminator byte
+	InvalidUtf8(FromUtf8Error),  // the bytes before the terminator are not valid UTF-8
+	InvalidLayout                // the sequence does not have the expected layout of fields
+}
+
+impl fmt::Display for DecodeError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			DecodeError::MissingTerminator => write!(f, "The sequence does not end with a string terminator."),
+			DecodeError::InvalidUtf8(ref err) => write!(f, "The sequence is not valid UTF-8: {}", err),
+			DecodeError::InvalidLayout => write!(f, "The sequence does not have the expected layout.")
+		}
+	}
+}
+
+impl Error for DecodeError {}
 
 // based on the paper:
 // R. Grossi, G. Ottoviano "The Wavelet Trie: Maintaining an Indexed Sequence of Strings in Compressed Space"
@@ -59,7 +87,7 @@ use self::bincode::{serialize_into, deserialize_from};
 /// wt.append_str("Hello everybody!");
 /// 
 /// // See on which positions the strings start with "Hell"
-/// assert_eq!(vec![0, 1], wt.select_all_str("Hell"));
+/// assert_eq!(vec![0, 1], wt.select_all_str_prefix("Hell"));
 /// 
 /// // get the string at position 1
 /// assert_eq!("Hello everybody!", wt.access_str(1).unwrap());
@@ -94,262 +122,278 @@ use self::bincode::{serialize_into, deserialize_from};
 /// assert_eq!(vec![2, 3], wt.select_all(&prefix_001));
 /// ```
 
-// a node in the wavelet trie
+// the nodes live in one arena; a node refers to its children by their index in it
 #[derive(Clone, Serialize, Deserialize)]
 pub struct WaveletTrie {
+	nodes: Vec<Node>,   // all nodes of the trie, and the free places left by delete
+	root: u32,          // the index of the root node
+	free: Vec<u32>      // indices of places in "nodes" that are not used
+}
+
+// a node in the wavelet trie
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Node {
 	prefix: DBVec,                  // α in the literature
-	positions: DBVec,               // β in the literature
-	left: Option<Box<WaveletTrie>>,   // left subtrie, if any
-	right: Option<Box<WaveletTrie>>   // right subtrie, if any
+	positions: Positions,           // β in the literature
+	children: Option<(u32, u32)>    // the indices of the left and right subtrie, if any
+}
 
+// what insert changes in the node at the end of its way down
+enum Change {
+	Fill,               // the trie is empty
+	Occurrence,         // one more occurrence in a leaf
+	Split(u64)          // the node splits, at the length of the lcp
+}
+
+impl Node {
+
+	// a node without children
+	fn leaf(prefix: DBVec, positions: Positions) -> Self {
+		Node {
+			prefix,
+			positions,
+			children: None
+		}
+	}
+
+	fn len(&self) -> u64 {
+		self.positions.len()
+	}
+
+	fn is_leaf(&self) -> bool {
+		self.children.is_none()
+	}
+
+	// the index of the left (false) or right (true) child
+	fn child(&self, bit: bool) -> Option<u32> {
+		self.children.map(|(left, right)| if bit { right } else { left })
+	}
 }
 
 impl WaveletTrie {
 
 	// constructor
 	pub fn new() -> Self {
-		WaveletTrie {
-			left: None,
-			right: None,
-			prefix: DBVec::new(),
-			positions: DBVec::new()
+		WaveletTrie::from_nodes(vec![Node::leaf(DBVec::new(), Positions::new())], 0)
+	}
+
+	// an empty trie with room for "nr_nodes" nodes, so bulk loading does not grow
+	// the arena again and again. A trie of n distinct sequences has 2n - 1 nodes.
+	pub fn with_capacity(nr_nodes: usize) -> Self {
+		let mut trie = WaveletTrie::new();
+		trie.reserve(nr_nodes);
+		trie
+	}
+
+	// makes room for at least "additional" more nodes than the trie has now
+	pub fn reserve(&mut self, additional: usize) {
+		let free = self.free.len();
+		if additional > free {
+			self.nodes.reserve(additional - free);
 		}
 	}
 
-	pub fn from_sequences(sequences: &[DBVec]) -> Self {
-		let mut wavelet_trie = WaveletTrie::new();
-		wavelet_trie.insert_static(sequences);
-		wavelet_trie
-	}
-
-	fn insert_static(&mut self, sequences: &[DBVec]) {
-		if !sequences.is_empty() {
-			let first_sequence = &sequences[0];
-			let all_equal = sequences.iter().all( |current_sequence| current_sequence == first_sequence);
-			if all_equal {
-				self.prefix = first_sequence.clone();
-				self.positions = DBVec::from_elem(sequences.len() as u64, false);
-			} else {
-				// create children
-				let mut left_child = WaveletTrie::new();
-				let mut right_child = WaveletTrie::new();
-				// find longest common prefix
-				self.prefix = first_sequence.clone();
-				for sequence in sequences {
-					let new_prefix = self.prefix.longest_common_prefix(sequence);
-					if new_prefix.len() < self.prefix.len() {
-						self.prefix = new_prefix;
-					}
-				}
-				// split accordingly
-				let mut left_sequences: Vec<DBVec> = Vec::new();
-				let mut right_sequences: Vec<DBVec> = Vec::new();
-				for sequence in sequences {
-					let (bit, suffix) = sequence.different_suffix(self.prefix.len());
-					self.positions.push(bit);
-					if bit {
-						right_sequences.push(suffix);
-					} else {
-						left_sequences.push(suffix);
-					}
+	// gives back the memory that is allocated but not used: the free places in
+	// the arena, which moves the nodes after them, and the unused room in the
+	// prefix and positions of every node
+	pub fn shrink_to_fit(&mut self) {
+		if !self.free.is_empty() {
+			// the new index of every used place, keeping their order
+			let mut new_ids = vec![0; self.nodes.len()];
+			let mut is_free = vec![false; self.nodes.len()];
+			for &id in &self.free {
+				is_free[id as usize] = true;
+			}
+			let mut next_id = 0;
+			for (id, new_id) in new_ids.iter_mut().enumerate() {
+				if !is_free[id] {
+					*new_id = next_id;
+					next_id += 1;
 				}
-				// now insert left and right sequences into subtrees
-				left_child.insert_static(&left_sequences);
-				right_child.insert_static(&right_sequences);
-				self.left = Some(Box::new(left_child));
-				self.right = Some(Box::new(right_child));
 			}
+			let nodes = mem::take(&mut self.nodes);
+			self.nodes = nodes.into_iter().enumerate()
+				.filter(|&(id, _)| !is_free[id])
+				.map(|(_, mut node)| {
+					node.children = node.children.map(|(left, right)| (new_ids[left as usize], new_ids[right as usize]));
+					node
+				}).collect();
+			self.root = new_ids[self.root as usize];
+			self.free = Vec::new();
+		}
+		self.nodes.shrink_to_fit();
+		self.free.shrink_to_fit();
+		for node in self.nodes.iter_mut() {
+			shrink_bits(&mut node.prefix);
+			node.positions.shrink_to_fit();
 		}
 	}
 
-	pub fn generate_graph(&self, out: &mut Write) {
-		self.generate_graph_internal(0, out);
+	// a trie of nodes built by a decoder, where every node but the root is a child once
+	fn from_nodes(nodes: Vec<Node>, root: u32) -> Self {
+		WaveletTrie {
+			nodes,
+			root,
+			free: Vec::new()
+		}
 	}
 
-	fn generate_graph_internal(&self, node_nr: u64, out: &mut Write) {
-		if node_nr == 0 {
-			out.write(b"digraph wavelet_trie {\n  graph [rankdir = \"LR\"]; node [shape = \"record\"];\n").unwrap();
-		}
+	fn node(&self, id: u32) -> &Node {
+		&self.nodes[id as usize]
+	}
 
-		out.write_fmt(format_args!(" \"{}\" [label = \"{}|pref len: {}|pref spars: {}|pos len:{}|pos spars: {}\"];\n",
-			node_nr,
-			node_nr,
-			self.prefix.len(),
-			self.prefix.sparseness(),
-			self.positions.len(),
-			self.positions.sparseness()
-		)).unwrap();
+	fn node_mut(&mut self, id: u32) -> &mut Node {
+		&mut self.nodes[id as usize]
+	}
 
-		if let Some(ref child) = self.left {
-			let child_nr = node_nr * 2 + 1;
-			child.generate_graph_internal(child_nr, out);
-			out.write_fmt(format_args!(" \"{}\" -> \"{}\"\n", node_nr, child_nr)).unwrap();
-		}
+	fn root_node(&self) -> &Node {
+		self.node(self.root)
+	}
 
-		if let Some(ref child) = self.right {
-			let child_nr = node_nr * 2 + 2;
-			child.generate_graph_internal(child_nr, out);
-			out.write_fmt(format_args!(" \"{}\" -> \"{}\"\n", node_nr, child_nr)).unwrap();
+	// puts a node in the arena, in a free place if there is one
+	fn add_node(&mut self, node: Node) -> u32 {
+		match self.free.pop() {
+			Some(id) => {
+				self.nodes[id as usize] = node;
+				id
+			},
+			None => push_node(&mut self.nodes, node)
 		}
+	}
 
-		if node_nr == 0 {
-			out.write(b"}\n").unwrap();
-		}
+	// takes a node out of the arena; its place can be used by the next add_node
+	fn remove_node(&mut self, id: u32) -> Node {
+		self.free.push(id);
+		mem::replace(self.node_mut(id), Node::leaf(DBVec::new(), Positions::new()))
 	}
 
-	pub fn print_stats(&self) -> (usize, usize, usize) { // nr subnodes, used bits, allocated bits
-		let mut nr_subnodes = 0;
-		let mut used_bits = (self.prefix.len() + self.positions.len()) as usize;
-		let mut allocated_bits = self.prefix.allocated_bytes() * 8 + self.positions.allocated_bytes() * 8 + 64;
-		//println!("prefix len: {}\tpositions len: {}\tallocated bits: {}\tused bits:{}", self.prefix.len(), self.positions.len(), allocated_bits, used_bits);
-		//println!("prefix: {:?}", self.prefix);
-		//println!("positions: {:?}", self.positions);
-		if let Some(ref child) = self.left {
-			let (nn, ub, ab) = child.print_stats();
-			used_bits += ub;
-			allocated_bits += ab;
-			nr_subnodes += 1 + nn;
-		}
-		if let Some(ref child) = self.right {
-			let (nn, ub, ab) = child.print_stats();
-			used_bits += ub;
-			allocated_bits += ab;
-			nr_subnodes += 1 + nn;
+	// builds the trie at once, which is much faster than appending the sequences
+	// one by one; see builder.rs
+	pub fn from_sequences(sequences: &[DBVec]) -> Self {
+		if sequences.is_empty() {
+			return WaveletTrie::new();
 		}
-		(nr_subnodes, used_bits, allocated_bits)
+		let (nodes, root) = build_nodes(sequences);
+		WaveletTrie::from_nodes(nodes, root)
 	}
 
 	// append a sequence to the trie at last position
 	pub fn append(&mut self, sequence: &DBVec) -> Result<(), &'static str> {
-		let index = self.positions.len();
+		let index = self.len();
 		self.insert(sequence, index)
 	}
 
 	pub fn insert(&mut self, sequence: &DBVec, index: u64) -> Result<(), &'static str> {
-		// 1. self.prefix is empty, no children:
+		// Going down from the root, with "rest" the part of sequence that is not matched yet:
+		// 1. the trie is empty:
 		//     self.prefix = sequence
-		// 2. self.prefix is empty, children:
-		//     2.a. sequence is empty:
-		//         ERROR: sequence a prefix of self.prefix
-		//     2.b. sequence not empty:
-		//         Take the first bit off of sequence; this determines whether the rest of sequence is inserted into the left or right child
-		// 3. self.prefix not empty:
-		//     3.a. sequence is empty:
-		//         ERROR: sequence is prefix of string already in the trie
-		//     3.b. self.prefix == sequence:
-		//         if no children: OK
-		//         if children: ERROR: sequence is prefix of string already in trie
-		//     3.c. sequence is prefix of self.prefix:
-		//         ERROR
-		//     3.d. self.prefix is prefix of sequence:
-		//         if children: substract self.prefix from sequence, take the first bit off of sequence; this determines whether the rest of sequence is inserted into the left or right child
-		//         if no children: ERROR
-		//     else:
-		//         (split current node; one child is existing trie and the other a new leaf)
-		//         calculate longest common prefix (lcp) of self.prefix and sequence
-		//         one new node has as prefix the suffix of self.prefix and the original children
-		//         one new node had as prefix the suffix of sequence and no children
-		//         self.prefix = lcp; self.left and self.right are the new nodes, determined by the first buit of the calculated suffixes
-
-		if self.prefix.is_empty() {
-			// case 1: empty prefix, no children
-			if self.left.is_none() {
-				self.prefix = sequence.copy();
-				self.positions.push(false);
-				return Ok(());
-
-			// case 2: empty prefix, children
-			} else {
-				if sequence.is_empty() {
-					return Err("The string being inserted is a prefix of a string in the trie, which is not allowed. (1)");
-				} else {
-					return self.insert_to_child(sequence, index);
-				}
-			}
+		// 2. rest == node.prefix:
+		//     if no children: OK, one more occurrence in this leaf
+		//     if children: ERROR: sequence is prefix of string already in trie
+		// 3. rest is a (shorter) prefix of node.prefix:
+		//     ERROR: sequence is prefix of string already in the trie
+		// 4. node.prefix is a (shorter) prefix of rest:
+		//     if children: substract node.prefix from rest, take the first bit off of rest; this determines whether the rest of sequence is inserted into the left or right child
+		//     if no children: ERROR
+		// 5. else:
+		//     (split the node; one child is the existing node and the other a new leaf)
+		//     calculate longest common prefix (lcp) of node.prefix and rest
+		//     one new node has as prefix the suffix of node.prefix and the original children
+		//     one new node had as prefix the suffix of rest and no children
+		//     node.prefix = lcp; node.left and node.right are the new nodes, determined by the first bit of the calculated suffixes
+		//
+		// The way down is checked first, so nothing changes if the sequence cannot be inserted.
+		let (path, offset, change) = self.find_insert(sequence)?;
+
+		// now change the trie: one more position in every node on the way
+		let mut id = self.root;
+		let mut index = index;
+		for bit in path {
+			let node = self.node_mut(id);
+			node.positions.insert(bit, index);
+			index = node.positions.rank(bit, index);
+			id = node.child(bit).unwrap();
 		}
-
-		// case 3: prefix is not empty
-		else {
-			if sequence.is_empty() {
-				return Err("The string being inserted is a prefix of a string in the trie, which is not allowed. (5)");
-			} else if &self.prefix == sequence {
-				if self.left.is_none() {
-					self.positions.insert(false, index);
-					return Ok(());
-				} else {
-					return Err("The string being inserted is a prefix of a string in the trie, which is not allowed. (2)");
-				}
-			} else if self.prefix.starts_with(&sequence) {
-				return Err("The string being inserted is a prefix of a string in the trie, which is not allowed. (3)");
-			} else if sequence.starts_with(&self.prefix) {
-				if self.left.is_none() {
-					//println!("prefix:\n>>{:?}, sequence:\n{:?}", self.prefix, sequence);
-					return Err("The string being inserted is a prefix of a string in the trie , which is not allowed. (4)");
-				} else {
-					return self.insert_to_child(sequence, index);
-				}
-			} else {
-				let lcp = sequence.longest_common_prefix(&self.prefix);
-				// bit_self determines wheter original node comes as left or right child in of new node
-				// suffix_self becomes prefix in new split node
-				let (bit_self, suffix_self) = self.prefix.different_suffix(lcp.len());
+		match change {
+			Change::Fill => {
+				let node = self.node_mut(id);
+				node.prefix = suffix_from(sequence, offset);
+				node.positions.push(false);
+			},
+			Change::Occurrence => self.node_mut(id).positions.insert(false, index),
+			Change::Split(lcp_len) => {
+				let rest = suffix_from(sequence, offset);
 				// suffix_seq becomes prefix in new leaf
-				let (bit_seq, suffix_seq) = sequence.different_suffix(lcp.len());
-
-				// reconstruct the original node
-				let original_left = self.left.take();
-				let original_right = self.right.take();
-				let original_positions = self.positions.copy();
-				let original_node = WaveletTrie {
-					left: original_left,
-					right: original_right,
-					prefix: suffix_self,
-					positions: original_positions
+				let (bit_seq, suffix_seq) = rest.different_suffix(lcp_len);
+				let (bit_self, original_node) = {
+					let node = self.node_mut(id);
+					// bit_self determines wheter original node comes as left or right child in of new node
+					// suffix_self becomes prefix in new split node
+					let (bit_self, suffix_self) = node.prefix.different_suffix(lcp_len);
+					let lcp = rest.longest_common_prefix(&node.prefix);
+					let pos_len = node.positions.len();
+
+					// reconstruct the original node
+					let original_node = Node {
+						prefix: suffix_self,
+						positions: mem::replace(&mut node.positions, Positions::from_elem(pos_len, bit_self)),
+						children: node.children.take()
+					};
+
+					// make this node the new node
+					node.prefix = lcp;
+					node.positions.insert(bit_seq, index);
+					(bit_self, original_node)
 				};
-
+				let original_id = self.add_node(original_node);
 				// create the leaf
-				let new_leaf = WaveletTrie {
-					left: None,
-					right: None,
-					prefix: suffix_seq,
-					positions: DBVec::from_elem(1, false)
+				let leaf_id = self.add_node(Node::leaf(suffix_seq, Positions::from_elem(1, false)));
+				self.node_mut(id).children = match bit_self {
+					false => Some((original_id, leaf_id)),
+					true => Some((leaf_id, original_id))
 				};
-
-				// make this node the new node
-				let (new_left, new_right) = match bit_self {
-					false => (Some(Box::new(original_node)), Some(Box::new(new_leaf))),
-					true => (Some(Box::new(new_leaf)), Some(Box::new(original_node)))
-				};
-				self.left = new_left;
-				self.right = new_right;
-				self.prefix = lcp;
-				let pos_len = self.positions.len();
-				self.positions = DBVec::from_elem(pos_len, bit_self);
-				self.positions.insert(bit_seq, index);
-
-				return Ok(());
 			}
 		}
+		Ok(())
 	}
 
-	fn insert_to_child(&mut self, sequence: &DBVec, index: u64) -> Result<(), &'static str> {
-		let (bit, suffix) = sequence.different_suffix(self.prefix.len());
-		self.positions.insert(bit, index);
-		let new_pos = self.positions.rank(bit, index);
-		match bit {
-			true => {
-				if let Some(ref mut child) = self.right {
-					child.insert(&suffix, new_pos)
+	// the way down for insert, without changing anything: the bits to the node that
+	// changes, where the rest of sequence starts, and the change; or why the
+	// sequence cannot be inserted
+	fn find_insert(&self, sequence: &DBVec) -> Result<(Vec<bool>, u64, Change), &'static str> {
+		let mut path = Vec::new();
+		let mut offset = 0;
+		let mut node = self.root_node();
+		loop {
+			if node.positions.is_empty() {
+				return Ok((path, offset, Change::Fill));
+			}
+			let rest_len = sequence.len() - offset;
+			let common_len = common_prefix_len(&node.prefix, sequence, offset);
+			if common_len == node.prefix.len() && common_len == rest_len {
+				if node.is_leaf() {
+					return Ok((path, offset, Change::Occurrence));
+				} else if node.prefix.is_empty() {
+					return Err("The string being inserted is a prefix of a string in the trie, which is not allowed. (1)");
 				} else {
-					Err("The right child has run away!")
+					return Err("The string being inserted is a prefix of a string in the trie, which is not allowed. (2)");
 				}
-			},
-			false => {
-				if let Some(ref mut child) = self.left {
-					child.insert(&suffix, new_pos)
+			} else if common_len == rest_len {
+				if rest_len == 0 {
+					return Err("The string being inserted is a prefix of a string in the trie, which is not allowed. (5)");
 				} else {
-					Err("The left child has run away!")
+					return Err("The string being inserted is a prefix of a string in the trie, which is not allowed. (3)");
 				}
+			} else if common_len == node.prefix.len() {
+				let bit = sequence.get(offset + common_len);
+				node = match node.child(bit) {
+					Some(child) => self.node(child),
+					None => return Err("The string being inserted is a prefix of a string in the trie , which is not allowed. (4)")
+				};
+				path.push(bit);
+				offset += common_len + 1;
+			} else {
+				return Ok((path, offset, Change::Split(common_len)));
 			}
 		}
 	}
@@ -357,300 +401,389 @@ impl WaveletTrie {
 	// counts the number of occurrences "sequence" (can be a prefix) up to index − 1.
 	// returns None if sequence does not occur
 	pub fn rank(&self, sequence: &DBVec, index: u64) -> Option<u64> {
-		if self.prefix.is_empty() && self.positions.is_empty() {
-			None
-		} else if sequence.is_empty() || sequence == &self.prefix {
-			Some(index)
-		} else if sequence.len() < self.prefix.len() {
-			// sequence has to be a prefix of "prefix"
-			// if so, return "index". If not, the sequence is not in the trie.
-			match self.prefix.starts_with(sequence) {
-				true => Some(index),
-				false => None
+		self.rank_bits(QueryBits::Bits(sequence), index)
+	}
+
+	// rank on the query bits, walking down with an offset into them, so nothing is allocated
+	fn rank_bits(&self, sequence: QueryBits, index: u64) -> Option<u64> {
+		let mut node = self.root_node();
+		if node.prefix.is_empty() && node.positions.is_empty() {
+			return None;
+		}
+		let mut offset = 0;
+		let mut index = index;
+		loop {
+			let rest_len = sequence.len() - offset;
+			let common_len = sequence.common_len(&node.prefix, offset);
+			if common_len == rest_len {
+				// the rest of sequence is empty, equal to "prefix" or a prefix of it
+				return Some(index);
+			} else if common_len < node.prefix.len() {
+				return None;
 			}
-		} else {
-			// "prefix" has to be a prefix of sequence
-			// if so, substract "prefix" from the beginning of sequence, and recurse!
-			match sequence.starts_with(&self.prefix) {
-				true => {
-					let (bit, suffix) = sequence.different_suffix(self.prefix.len());
-					let new_index = self.positions.rank(bit, index);
-					match bit {
-						true => {
-							match self.right {
-								Some(ref trie) => trie.rank(&suffix, new_index),
-								None => Some(new_index)
-							}
-						},
-						false => {
-							match self.left {
-								Some(ref trie) => trie.rank(&suffix, new_index),
-								None => Some(new_index)
-							}
-						}
-					}
-				},
-				false => None
+			// "prefix" is a prefix of the rest of sequence: go on in a child
+			let bit = sequence.get(offset + common_len);
+			index = node.positions.rank(bit, index);
+			node = match node.child(bit) {
+				Some(child) => self.node(child),
+				None => return Some(index)
+			};
+			offset += common_len + 1;
+		}
+	}
+
+	// counts the occurrences of "sequence" (can be a prefix) up to each of the
+	// indices − 1, like rank, but finds the way down only once.
+	// returns None if sequence does not occur
+	pub fn rank_many(&self, sequence: &DBVec, indices: &[u64]) -> Option<Vec<u64>> {
+		let path = self.rank_path(QueryBits::Bits(sequence))?;
+		let mut ranks = indices.to_vec();
+		for &(node, bit) in &path {
+			for rank in ranks.iter_mut() {
+				*rank = node.positions.rank(bit, *rank);
 			}
 		}
+		Some(ranks)
+	}
+
+	// the nodes and bits rank goes through, in that order; None if sequence does not occur
+	fn rank_path(&self, sequence: QueryBits) -> Option<Vec<(&Node, bool)>> {
+		let mut node = self.root_node();
+		if node.prefix.is_empty() && node.positions.is_empty() {
+			return None;
+		}
+		let mut path = Vec::new();
+		let mut offset = 0;
+		loop {
+			let rest_len = sequence.len() - offset;
+			let common_len = sequence.common_len(&node.prefix, offset);
+			if common_len == rest_len {
+				return Some(path);
+			} else if common_len < node.prefix.len() {
+				return None;
+			}
+			let bit = sequence.get(offset + common_len);
+			path.push((node, bit));
+			node = match node.child(bit) {
+				Some(child) => self.node(child),
+				None => return Some(path)
+			};
+			offset += common_len + 1;
+		}
 	}
 
 	pub fn len(&self) -> u64 {
-		self.positions.len()
+		self.root_node().len()
+	}
+
+	// enumerates the distinct prefixes of "depth" bits of the sequences in the trie,
+	// together with the number of sequences starting with each of them.
+	// the prefixes come in trie order (0 before 1); sequences shorter than "depth"
+	// bits are reported as a whole.
+	pub fn distinct_prefixes(&self, depth: u64) -> Vec<(DBVec, u64)> {
+		let mut result = Vec::new();
+		if self.len() == 0 {
+			return result;
+		}
+		// the nodes to visit, with the bits before their prefix; right is pushed before left
+		let mut stack = vec![(self.root_node(), DBVec::new())];
+		while let Some((node, mut path)) = stack.pop() {
+			let mut bit_nr = 0;
+			while bit_nr < node.prefix.len() && path.len() < depth {
+				path.push(node.prefix.get(bit_nr));
+				bit_nr += 1;
+			}
+			match node.children {
+				Some((left, right)) if path.len() < depth => {
+					let mut left_path = path.copy();
+					left_path.push(false);
+					path.push(true);
+					stack.push((self.node(right), path));
+					stack.push((self.node(left), left_path));
+				},
+				_ => result.push((path, node.len()))
+			}
+		}
+		result
 	}
 
 	// retrieve the sequence at the given index
 	pub fn access(&self, index: u64) -> DBVec {
-		let mut result = self.prefix.copy();
-		if self.left.is_some() {	// if NO children, the position vector doesn't count...
-			let bit = self.positions.get(index);
-			let new_index = self.positions.rank(bit, index);
-			result.push(bit);
-			match bit {
-				true => {
-					if let Some(ref trie) = self.right {
-						result.append_vec(&mut trie.access(new_index));
-					}
-				},
-				false => {
-					if let Some(ref trie) = self.left {
-						result.append_vec(&mut trie.access(new_index));
-					}
-				}
+		let mut result = DBVec::new();
+		let mut node = self.root_node();
+		let mut index = index;
+		loop {
+			result.append_vec(&mut node.prefix.copy());
+			// if NO children, the position vector doesn't count...
+			let bit = match node.children {
+				Some(_) => node.positions.get(index),
+				None => return result
 			};
+			index = node.positions.rank(bit, index);
+			result.push(bit);
+			node = self.node(node.child(bit).unwrap());
+		}
+	}
+
+	// the nodes and bits on the way to the node where "sequence" matches (is equal
+	// to or a prefix of the bits up to and with the prefix of the node), and that node.
+	// None if sequence is not in the trie.
+	fn find_path(&self, sequence: QueryBits) -> Option<(Vec<(&Node, bool)>, &Node)> {
+		let mut path = Vec::new();
+		let mut node = self.root_node();
+		let mut offset = 0;
+		loop {
+			let rest_len = sequence.len() - offset;
+			let common_len = sequence.common_len(&node.prefix, offset);
+			if common_len == rest_len {
+				// OK, found!
+				return Some((path, node));
+			} else if common_len < node.prefix.len() {
+				// domage, sequence not in trie!
+				return None;
+			}
+			// search further
+			let bit = sequence.get(offset + common_len);
+			let child = self.node(node.child(bit)?);
+			path.push((node, bit));
+			node = child;
+			offset += common_len + 1;
 		}
-		result
 	}
 
 	// find the position of the occurrence_nr-th given sequence (can be a prefix)
 	// an occurrence number starts at 1 (a zero-th occurrence makes no sense)
 	// returns None if not found.
 	pub fn select(&self, sequence: &DBVec, occurrence_nr: u64) -> Option<u64> {
-		// find recursively until node where sequence matches or is prefix of self.prefix.
-		// upon return, calculate back the positions of [bit], depending on the value of bit.
-		if sequence.is_empty() || sequence == &self.prefix || self.prefix.starts_with(sequence) {
-			// OK, found!
-			Some(occurrence_nr - 1)	// -1 due to +1 offset of occurrence_nr
-		} else if sequence.starts_with(&self.prefix) {
-			if self.left.is_none() {
-				// domage, sequence not in trie!
-				None
-			} else {
-				// search further
-				let (bit, suffix) = sequence.different_suffix(self.prefix.len());
-
-				// closure that is used to calculate the new position.
-				let calc_new_pos = |trie: &WaveletTrie| {
-					let pos_option = trie.select(&suffix, occurrence_nr);
-					match pos_option {
-						Some(pos) => self.positions.select(bit, pos + 1),
-						None => None
-					}
-				};
-				match bit {
-					true => {
-						if let Some(ref trie) = self.right { // is always true in this case
-							return calc_new_pos(trie);
-						}
-					},
-					false => {
-						if let Some(ref trie) = self.left { // is always true in this case
-							return calc_new_pos(trie);
-						}
-					}
-				}
-				// should never come here. Maybe panic?
-				None
-			}
-		} else {
-			// domage, sequence not in trie!
-			None
+		// find the node where sequence matches, then calculate back the
+		// positions of [bit] on the way up, depending on the value of bit.
+		self.select_bits(QueryBits::Bits(sequence), occurrence_nr)
+	}
+
+	fn select_bits(&self, sequence: QueryBits, occurrence_nr: u64) -> Option<u64> {
+		let (path, _) = self.find_path(sequence)?;
+		let mut position = occurrence_nr - 1;	// -1 due to +1 offset of occurrence_nr
+		for &(node, bit) in path.iter().rev() {
+			position = node.positions.select(bit, position + 1)?;
 		}
+		Some(position)
 	}
 
 	// find the positions of all occurrences of the given sequence (can be prefix)
 	pub fn select_all(&self, sequence: &DBVec) -> Vec<u64> {
-		if sequence.is_empty() || sequence == &self.prefix || self.prefix.starts_with(sequence) {
-			// found! return vector [0, 1, ... positions.len() - 1]
-			let mut result = Vec::with_capacity(self.positions.len() as usize);
-			for i in 0..self.positions.len() {
-				result.push(i);
-			}
-			result
-		} else if sequence.starts_with(&self.prefix) {
-			if self.left.is_none() {
-				// domage, sequence not in trie!
-				Vec::new()
-			} else {
-				// search further
-				let (bit, suffix) = sequence.different_suffix(self.prefix.len());
-
-				// closure that is used to calculate the new position.
-				let calc_new_pos = |trie: &WaveletTrie| {
-					let mut all_positions = trie.select_all(&suffix);	// positions is now a Vec
-					for position in all_positions.iter_mut() {
-						let new_position = self.positions.select(bit, *position + 1);
-						match new_position {
-							Some(new_pos) => {*position = new_pos;},
-							None => {panic!("This cannot happen!");},
-						};
-					}
-					all_positions
-				};
+		self.select_all_bits(QueryBits::Bits(sequence))
+	}
 
-				match bit {
-					true => {
-						if let Some(ref trie) = self.right { // is always true in this case
-							return calc_new_pos(trie);
-						}
-					},
-					false => {
-						if let Some(ref trie) = self.left { // is always true in this case
-							return calc_new_pos(trie);
-						}
-					}
-				}
-				// should never come here. Maybe panic?
-				Vec::new()
+	fn select_all_bits(&self, sequence: QueryBits) -> Vec<u64> {
+		let (path, found) = match self.find_path(sequence) {
+			Some(found) => found,
+			None => return Vec::new()
+		};
+		// found! start with [0, 1, ... positions.len() - 1] and calculate back.
+		// The positions stay sorted, so every level maps them all in one pass
+		let mut all_positions: Vec<u64> = (0..found.positions.len()).collect();
+		for &(node, bit) in path.iter().rev() {
+			node.positions.select_sorted(bit, &mut all_positions);
+		}
+		all_positions
+	}
+
+	// counts all occurrences of the given sequence (can be prefix), without finding their positions
+	pub fn count(&self, sequence: &DBVec) -> u64 {
+		self.count_bits(QueryBits::Bits(sequence))
+	}
+
+	fn count_bits(&self, sequence: QueryBits) -> u64 {
+		match self.find_path(sequence) {
+			Some((_, found)) => found.len(),
+			None => 0
+		}
+	}
+
+	// finds the positions of the given occurrences of sequence (can be a prefix),
+	// like select, but finds the way down only once. An occurrence number
+	// starts at 1; None for occurrences that do not exist.
+	pub fn select_many(&self, sequence: &DBVec, occurrence_nrs: &[u64]) -> Vec<Option<u64>> {
+		let (path, found) = match self.find_path(QueryBits::Bits(sequence)) {
+			Some(found) => found,
+			None => return vec![None; occurrence_nrs.len()]
+		};
+		let mut positions: Vec<Option<u64>> = occurrence_nrs.iter().map(|&occurrence_nr| {
+			if occurrence_nr == 0 || occurrence_nr > found.len() { None } else { Some(occurrence_nr - 1) }
+		}).collect();
+		for &(node, bit) in path.iter().rev() {
+			for position in positions.iter_mut() {
+				*position = position.and_then(|position| node.positions.select(bit, position + 1));
 			}
-		} else {
-			// domage, sequence not in trie!
-			Vec::new()
 		}
+		positions
 	}
 
 	pub fn delete(&mut self, index: u64) {
-		let bit = self.positions.get(index);
-		let new_pos = self.positions.rank(bit, index);
-		match bit {
-			true => {
-				let mut delete_child = false;
-				if let Some(ref mut child) = self.right {
-					child.delete(new_pos);
-					if child.len() == 0 {	// this would be the leaf node
-						delete_child = true;
-					}
-				}
-				if delete_child {
-					self.right = None;
-					self.prefix.push(!bit);
-					// merge other child with this node
-					let mut new_left: Option<Box<WaveletTrie>> = None;
-					let mut new_right: Option<Box<WaveletTrie>> = None;
-					if let Some(ref mut child) = self.left {
-						self.prefix.append_vec(&mut child.prefix);
-						new_left = child.left.take();
-						new_right = child.right.take();
+		let mut id = self.root;
+		let mut index = index;
+		loop {
+			let (bit, new_pos, child) = {
+				let node = self.node_mut(id);
+				if node.is_leaf() {
+					node.positions.delete(index);
+					if node.len() == 0 {
+						// the trie is in fact empty!
+						node.prefix = DBVec::new();
 					}
-					self.left = new_left;
-					self.right = new_right;
+					// if no children, set the positions all to zero
+					node.positions.set_none();
+					return;
 				}
-			},
-			false => {
-				let mut delete_child = false;
-				if let Some(ref mut child) = self.left {
-					child.delete(new_pos);
-					if child.len() == 0 {	// this would be the leaf node
-						delete_child = true;
-					}
-				}
-				if delete_child {
-					self.left = None;
-					self.prefix.push(!bit);
-					// merge other child with this node
-					let mut new_left: Option<Box<WaveletTrie>> = None;
-					let mut new_right: Option<Box<WaveletTrie>> = None;
-					if let Some(ref mut child) = self.right {
-						self.prefix.append_vec(&mut child.prefix);
-						new_left = child.left.take();
-						new_right = child.right.take();
-					}
-					self.left = new_left;
-					self.right = new_right;
+				let bit = node.positions.get(index);
+				(bit, node.positions.rank(bit, index), node.child(bit).unwrap())
+			};
+			let child_disappears = {
+				let child = self.node(child);
+				child.is_leaf() && child.len() == 1
+			};
+			if child_disappears {
+				// merge the other child with this node; it holds all remaining sequences, in the same order
+				let other_id = self.node(id).child(!bit).unwrap();
+				self.remove_node(child);
+				let mut other = self.remove_node(other_id);
+				let node = self.node_mut(id);
+				node.prefix.push(!bit);
+				node.prefix.append_vec(&mut other.prefix);
+				node.children = other.children;
+				node.positions = other.positions;
+				if node.is_leaf() { // if no children, set the positions all to zero
+					node.positions.set_none();
 				}
+				return;
 			}
-		}
-		self.positions.delete(index);
-		if self.len() == 0 {
-			// the trie is in fact empty!
-			self.prefix = DBVec::new();
-		}
-		if self.left.is_none() { // if no children, set the positions all to zero
-			self.positions.set_none();
+			self.node_mut(id).positions.delete(index);
+			index = new_pos;
+			id = child;
 		}
 	}
 
-	// appends a string to the trie
+	// appends a string to the trie. A terminator is added, so the string can
+	// be found back with the *_str_exact methods.
 	pub fn append_str(&mut self, text: &str) -> Result<(), &'static str> {
 		self.append(&Self::text_to_bitvec(text))
 	}
 
-	// counts the number of occurrences "text" (can be a prefix) up to index - 1.
+	// counts the number of occurrences of exactly "text" up to index - 1.
 	// returns None if the string does not occur
-	pub fn rank_str(&self, text: &str, index: u64) -> Option<u64> {
-		let sequence = DBVec::from_bytes(text.as_bytes());
-		self.rank(&sequence, index)
+	pub fn rank_str_exact(&self, text: &str, index: u64) -> Option<u64> {
+		self.rank_bits(QueryBits::Text(text.as_bytes(), true), index)
 	}
 
-	// retrieves the string at the given index
-	pub fn access_str(&self, index: u64) -> Result<String, FromUtf8Error> {
+	// counts the number of strings starting with "text" up to index - 1.
+	// returns None if no string starts with "text"
+	pub fn rank_str_prefix(&self, text: &str, index: u64) -> Option<u64> {
+		self.rank_bits(QueryBits::Text(text.as_bytes(), false), index)
+	}
+
+	// retrieves the string at the given index.
+	// returns an error if the stored sequence is not a terminated UTF-8 string
+	pub fn access_str(&self, index: u64) -> Result<String, DecodeError> {
 		let sequence = self.access(index);
 		Self::bitvec_to_text(&sequence)
 	}
 
-	// finds the position of the occurrence_nr-th given string (can be a prefix)
+	// finds the position of the occurrence_nr-th occurrence of exactly "text"
 	// an occurrence number starts at 1 (a zero-th occurrence makes no sense)
 	// returns None if not found.
-	pub fn select_str(&self, text: &str, occurrence_nr: u64) -> Option<u64> {
-		let sequence = DBVec::from_bytes(text.as_bytes());
-		self.select(&sequence, occurrence_nr)
+	pub fn select_str_exact(&self, text: &str, occurrence_nr: u64) -> Option<u64> {
+		self.select_bits(QueryBits::Text(text.as_bytes(), true), occurrence_nr)
+	}
+
+	// finds the position of the occurrence_nr-th string starting with "text"
+	// an occurrence number starts at 1 (a zero-th occurrence makes no sense)
+	// returns None if not found.
+	pub fn select_str_prefix(&self, text: &str, occurrence_nr: u64) -> Option<u64> {
+		self.select_bits(QueryBits::Text(text.as_bytes(), false), occurrence_nr)
+	}
+
+	// finds the positions of all occurrences of exactly "text"
+	pub fn select_all_str_exact(&self, text: &str) -> Vec<u64> {
+		self.select_all_bits(QueryBits::Text(text.as_bytes(), true))
+	}
+
+	// finds the positions of all strings starting with "text"
+	pub fn select_all_str_prefix(&self, text: &str) -> Vec<u64> {
+		self.select_all_bits(QueryBits::Text(text.as_bytes(), false))
+	}
+
+	// counts the occurrences of exactly "text"
+	pub fn count_str_exact(&self, text: &str) -> u64 {
+		self.count_bits(QueryBits::Text(text.as_bytes(), true))
+	}
+
+	// counts the strings starting with "text"
+	pub fn count_str_prefix(&self, text: &str) -> u64 {
+		self.count_bits(QueryBits::Text(text.as_bytes(), false))
 	}
 
 	fn text_to_bitvec(text: &str) -> DBVec {
-		let text_bytes = text.as_bytes();
-		let mut text_bitvec = DBVec::from_bytes(text_bytes);
+		let mut text_bitvec = Self::prefix_to_bitvec(text);
 		// add the terminator!
-		let mut end_symbol = DBVec::from_bytes(&[0b0]);
+		let mut end_symbol = DBVec::from_bytes(&[TERMINATOR]);
 		text_bitvec.append_vec(&mut end_symbol);
 		text_bitvec
 	}
 
-	// finds the positions of all occurrences of the given sequence (can be prefix)
-	pub fn select_all_str(&self, text: &str) -> Vec<u64> {
-		let sequence = DBVec::from_bytes(text.as_bytes());
-		self.select_all(&sequence)
+	// no terminator here: the result matches every string starting with "text"
+	fn prefix_to_bitvec(text: &str) -> DBVec {
+		DBVec::from_bytes(text.as_bytes())
 	}
 
-	fn bitvec_to_text(sequence: &DBVec) -> Result<String, FromUtf8Error> {
+	fn bitvec_to_text(sequence: &DBVec) -> Result<String, DecodeError> {
+		if !sequence.len().is_multiple_of(8) {
+			return Err(DecodeError::MissingTerminator);
+		}
 		let mut bytes = sequence.to_bytes();
-		// destroy the terminator!
-		bytes.pop();
-		String::from_utf8(bytes)
-	}
-
-	fn fmt_pretty(&self, f: &mut fmt::Formatter, level: usize) -> fmt::Result {
-		let indent = String::from_utf8(vec![32; level * 3]).unwrap();
-		let _a = write!(f, "{}len      : {}\n", indent, self.len());
-		let _b = write!(f, "{}prefix   : {:?}\n", indent, self.prefix);
-		let _c = write!(f, "{}positions: {:?}\n", indent, self.positions);
-		let _d = match self.left {
-			None => write!(f, "{}left     : none\n", indent),
-			Some(ref child) => {
-				let _ = write!(f, "{}left (\n", indent);
-				let _ = child.fmt_pretty(f, level + 1);
-				write!(f, "{})\n", indent)
-			}
-		};
-		match self.right {
-			None => write!(f, "{}right    : none\n", indent),
-			Some(ref child) => {
-				let _ = write!(f, "{}right (\n", indent);
-				let _ = child.fmt_pretty(f, level + 1);
-				write!(f, "{})\n", indent)
+		// destroy the terminator, but only if it is really there!
+		match bytes.pop() {
+			Some(TERMINATOR) => String::from_utf8(bytes).map_err(DecodeError::InvalidUtf8),
+			_ => Err(DecodeError::MissingTerminator)
+		}
+	}
+
+	fn fmt_pretty(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		enum Step<'a> {
+			Node(&'a Node, usize),
+			Line(String)
+		}
+		let mut steps = vec![Step::Node(self.root_node(), 0)];
+		while let Some(step) = steps.pop() {
+			match step {
+				Step::Line(line) => write!(f, "{}", line)?,
+				Step::Node(node, level) => {
+					let indent = String::from_utf8(vec![32; level * 3]).unwrap();
+					writeln!(f, "{}len      : {}", indent, node.len())?;
+					writeln!(f, "{}prefix   : {:?}", indent, node.prefix)?;
+					writeln!(f, "{}positions: {:?}", indent, node.positions)?;
+					// what comes last is pushed first
+					match node.children {
+						None => {
+							steps.push(Step::Line(format!("{}right    : none\n", indent)));
+							steps.push(Step::Line(format!("{}left     : none\n", indent)));
+						},
+						Some((left, right)) => {
+							steps.push(Step::Line(format!("{})\n", indent)));
+							steps.push(Step::Node(self.node(right), level + 1));
+							steps.push(Step::Line(format!("{}right (\n", indent)));
+							steps.push(Step::Line(format!("{})\n", indent)));
+							steps.push(Step::Node(self.node(left), level + 1));
+							steps.push(Step::Line(format!("{}left (\n", indent)));
+						}
+					}
+				}
 			}
 		}
+		Ok(())
 	}
 
+	// dumps the structure as is with bincode. The result depends on the internals
+	// of DBVec; use save and load for files that have to be read back later.
 	pub fn serialize(&self, writer: &mut Write) -> bincode::Result<()> {
 		serialize_into(writer, self)
 	}
@@ -662,8 +795,85 @@ impl WaveletTrie {
 
 impl fmt::Debug for WaveletTrie {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-		self.fmt_pretty(f, 0)
+		self.fmt_pretty(f)
+	}
+}
+
+// adds a node at the end of an arena, and returns its index
+fn push_node(nodes: &mut Vec<Node>, node: Node) -> u32 {
+	assert!(nodes.len() < u32::MAX as usize, "A wavelet trie has at most 2^32 - 1 nodes.");
+	nodes.push(node);
+	(nodes.len() - 1) as u32
+}
+
+// the number of bits "prefix" has in common with "sequence" from bit "offset" on
+fn common_prefix_len(prefix: &DBVec, sequence: &DBVec, offset: u64) -> u64 {
+	QueryBits::Bits(sequence).common_len(prefix, offset)
+}
+
+// the bits a query looks for, borrowed: a sequence, or the bytes of a string,
+// with a terminator after them if the string has to match exactly. Queries
+// keep an offset into these bits instead of cutting off suffixes.
+#[derive(Clone, Copy)]
+enum QueryBits<'a> {
+	Bits(&'a DBVec),
+	Text(&'a [u8], bool)
+}
+
+impl<'a> QueryBits<'a> {
+	fn len(&self) -> u64 {
+		match *self {
+			QueryBits::Bits(sequence) => sequence.len(),
+			QueryBits::Text(bytes, terminated) => (bytes.len() as u64 + if terminated { 1 } else { 0 }) * 8
+		}
+	}
+
+	// the bits of a byte go from the least significant one up, as in DBVec::from_bytes
+	fn get(&self, index: u64) -> bool {
+		match *self {
+			QueryBits::Bits(sequence) => sequence.get(index),
+			QueryBits::Text(bytes, _) => {
+				let byte = bytes.get((index / 8) as usize).cloned().unwrap_or(TERMINATOR);
+				byte & (1 << (index % 8)) != 0
+			}
+		}
+	}
+
+	// the number of bits "prefix" has in common with these bits from bit "offset" on
+	fn common_len(&self, prefix: &DBVec, offset: u64) -> u64 {
+		let max_len = cmp::min(prefix.len(), self.len() - offset);
+		let mut len = 0;
+		while len < max_len && prefix.get(len) == self.get(offset + len) {
+			len += 1;
+		}
+		len
+	}
+}
+
+// the bits of "sequence" from bit "offset" on
+fn suffix_from(sequence: &DBVec, offset: u64) -> DBVec {
+	if offset == 0 {
+		sequence.copy()
+	} else {
+		sequence.different_suffix(offset - 1).1
 	}
 }
 
+pub mod normalized;
+pub mod suffix_index;
+pub mod composite;
+pub mod timestamp;
+pub mod file_format;
+pub mod mapped;
+pub mod static_trie;
+pub mod persistent;
+pub mod streaming;
+pub mod text_io;
+pub mod json;
+pub mod graph;
+pub mod builder;
+pub mod batch;
+pub mod stats;
+mod positions;
+
 mod tests;
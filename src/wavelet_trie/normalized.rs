@@ -0,0 +1,151 @@
+extern crate unicode_normalization;
+extern crate caseless;
+
+use self::unicode_normalization::UnicodeNormalization;
+use self::caseless::default_case_fold_str;
+use wavelet_trie::{WaveletTrie, DecodeError};
+
+/// A wavelet trie for strings that are compared case-insensitively and
+/// independent of their Unicode normalization form.
+///
+/// Every string is turned into a _key_ before it is stored or looked up: it is
+/// case folded with the full Unicode case folding and brought into Unicode
+/// normalization form C (NFC). This way "Müller", "MÜLLER" and the decomposed
+/// "Mu\u{308}ller" all map to the key "müller", "STRASSE" and "Straße" both map
+/// to "strasse", and exact or prefix queries on any of these spellings find all of them.
+///
+/// Optionally, the original spelling of each string is kept in a second wavelet trie,
+/// aligned position by position with the keys.
+///
+/// # Examples
+///
+/// ```rust
+/// extern crate wavelet_trie;
+///
+/// use wavelet_trie::wavelet_trie::normalized::NormalizedWaveletTrie;
+///
+/// let mut wt = NormalizedWaveletTrie::keeping_originals();
+/// wt.append_str("Müller").unwrap();
+/// wt.append_str("Mu\u{308}ller").unwrap();
+/// wt.append_str("Meyer").unwrap();
+///
+/// assert_eq!(vec![0, 1], wt.select_all_str_exact("müller"));
+/// assert_eq!(vec![0, 1, 2], wt.select_all_str_prefix("M"));
+/// assert_eq!("Mu\u{308}ller", wt.access_str(1).unwrap());
+/// assert_eq!("müller", wt.access_key(1).unwrap());
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NormalizedWaveletTrie {
+	keys: WaveletTrie,                 // the normalized keys
+	originals: Option<WaveletTrie>     // the original spellings, if kept
+}
+
+impl Default for NormalizedWaveletTrie {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl NormalizedWaveletTrie {
+
+	// constructor; only the normalized keys are stored
+	pub fn new() -> Self {
+		NormalizedWaveletTrie {
+			keys: WaveletTrie::new(),
+			originals: None
+		}
+	}
+
+	// constructor; the original spelling of every string is stored as well
+	pub fn keeping_originals() -> Self {
+		NormalizedWaveletTrie {
+			keys: WaveletTrie::new(),
+			originals: Some(WaveletTrie::new())
+		}
+	}
+
+	// turns a string into the key that is stored in the trie: case folded and in
+	// Unicode normalization form C. Folding the decomposed string also folds the
+	// combining marks, as the canonical caseless match of Unicode does
+	pub fn normalize(text: &str) -> String {
+		default_case_fold_str(&text.nfd().collect::<String>()).nfc().collect()
+	}
+
+	pub fn keeps_originals(&self) -> bool {
+		self.originals.is_some()
+	}
+
+	pub fn len(&self) -> u64 {
+		self.keys.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	// appends a string to the trie at last position
+	pub fn append_str(&mut self, text: &str) -> Result<(), &'static str> {
+		let index = self.len();
+		self.insert_str(text, index)
+	}
+
+	pub fn insert_str(&mut self, text: &str, index: u64) -> Result<(), &'static str> {
+		let key = WaveletTrie::text_to_bitvec(&Self::normalize(text));
+		self.keys.insert(&key, index)?;
+		if let Some(ref mut originals) = self.originals {
+			if let Err(reason) = originals.insert(&WaveletTrie::text_to_bitvec(text), index) {
+				// keep keys and originals aligned
+				self.keys.delete(index);
+				return Err(reason);
+			}
+		}
+		Ok(())
+	}
+
+	pub fn delete(&mut self, index: u64) {
+		self.keys.delete(index);
+		if let Some(ref mut originals) = self.originals {
+			originals.delete(index);
+		}
+	}
+
+	// retrieves the string at the given index in its original spelling if
+	// originals are kept, or else its normalized key
+	pub fn access_str(&self, index: u64) -> Result<String, DecodeError> {
+		match self.originals {
+			Some(ref originals) => originals.access_str(index),
+			None => self.keys.access_str(index)
+		}
+	}
+
+	// retrieves the normalized key at the given index
+	pub fn access_key(&self, index: u64) -> Result<String, DecodeError> {
+		self.keys.access_str(index)
+	}
+
+	// counts the number of strings equal to "text" after normalization, up to index - 1
+	pub fn rank_str_exact(&self, text: &str, index: u64) -> Option<u64> {
+		self.keys.rank_str_exact(&Self::normalize(text), index)
+	}
+
+	// counts the number of strings starting with "text" after normalization, up to index - 1
+	pub fn rank_str_prefix(&self, text: &str, index: u64) -> Option<u64> {
+		self.keys.rank_str_prefix(&Self::normalize(text), index)
+	}
+
+	pub fn select_str_exact(&self, text: &str, occurrence_nr: u64) -> Option<u64> {
+		self.keys.select_str_exact(&Self::normalize(text), occurrence_nr)
+	}
+
+	pub fn select_str_prefix(&self, text: &str, occurrence_nr: u64) -> Option<u64> {
+		self.keys.select_str_prefix(&Self::normalize(text), occurrence_nr)
+	}
+
+	pub fn select_all_str_exact(&self, text: &str) -> Vec<u64> {
+		self.keys.select_all_str_exact(&Self::normalize(text))
+	}
+
+	pub fn select_all_str_prefix(&self, text: &str) -> Vec<u64> {
+		self.keys.select_all_str_prefix(&Self::normalize(text))
+	}
+}
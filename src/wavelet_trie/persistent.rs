@@ -0,0 +1,481 @@
+// A wavelet trie that keeps itself on disk: a snapshot in the file format of
+// file_format.rs, and an append-only log of the operations done since.
+//
+// A directory holds the files of one trie, numbered by generation g:
+//
+//   snapshot-g.wt   the trie at the last checkpoint (succinct encoding); absent for generation 0
+//   wal-g.log       the operations after that checkpoint
+//
+// The log starts with a header, followed by one record per operation.
+// All integers are little endian.
+//
+// offset  size  field
+//      0     8  magic bytes "WAVELOG\0"
+//      8     2  format version (currently 1)
+//     10     6  reserved, 0
+//     16     8  generation g
+//
+// record:
+//   1 byte   operation: 1 insert, 2 delete, 3 set
+//   8 bytes  index
+//   8 bytes  number of bits in the sequence (l); insert and set only
+//   ⌈l/8⌉    sequence bits, least significant bit first; insert and set only
+//   4 bytes  CRC-32 (IEEE) of the preceding bytes of the record
+//
+// A crash while a record is written leaves an incomplete record at the end
+// of the log. It has no valid checksum; on open it is cut off, so the trie
+// is as it was after the last complete operation. A damaged record with more
+// of the log after it is not a crash: open fails, and the log is left as it is.
+//
+// A checkpoint writes snapshot-(g+1).wt under a temporary name and renames it,
+// then starts the empty wal-(g+1).log and removes the files of generation g.
+// On open the newest snapshot wins, so a crash at any point during a
+// checkpoint loses nothing.
+
+extern crate dyn_bit_vec;
+extern crate crc32fast;
+
+use self::dyn_bit_vec::DBVec;
+use self::crc32fast::Hasher;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use wavelet_trie::WaveletTrie;
+use wavelet_trie::file_format::{FormatError, Encoding, bits_to_bytes, bytes_to_bits, u16_to_bytes, u64_to_bytes, bytes_to_u16, bytes_to_u32, bytes_to_u64, u32_to_bytes};
+
+const LOG_MAGIC: &[u8; 8] = b"WAVELOG\0";
+const LOG_VERSION: u16 = 1;
+const LOG_HEADER_LEN: usize = 24;
+
+const OP_INSERT: u8 = 1;
+const OP_DELETE: u8 = 2;
+const OP_SET: u8 = 3;
+
+// reasons why an operation on a persistent trie fails
+#[derive(Debug)]
+pub enum PersistError {
+	Format(FormatError),      // reading or writing the snapshot or the log failed
+	Rejected(&'static str)    // the operation is not valid on the trie; nothing changed
+}
+
+impl fmt::Display for PersistError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			PersistError::Format(ref err) => write!(f, "{}", err),
+			PersistError::Rejected(reason) => write!(f, "Operation rejected: {}", reason)
+		}
+	}
+}
+
+impl Error for PersistError {}
+
+impl From<FormatError> for PersistError {
+	fn from(err: FormatError) -> Self {
+		PersistError::Format(err)
+	}
+}
+
+impl From<io::Error> for PersistError {
+	fn from(err: io::Error) -> Self {
+		PersistError::Format(FormatError::Io(err))
+	}
+}
+
+// what the next record of the log holds
+enum Record {
+	Complete(Operation, u64),   // an operation, and the length of its record
+	Damaged(u64),               // a record with a wrong checksum or operation, and the bytes read of it
+	End                         // the log ends here, or within the record
+}
+
+// an operation as it is logged
+#[derive(Debug)]
+enum Operation {
+	Insert(u64, DBVec),
+	Delete(u64),
+	Set(u64, DBVec)
+}
+
+impl Operation {
+
+	// does the operation on the trie; on an error the trie is unchanged
+	fn apply(&self, trie: &mut WaveletTrie) -> Result<(), &'static str> {
+		match *self {
+			Operation::Insert(index, ref sequence) => {
+				if index > trie.len() {
+					return Err("The index is out of range.");
+				}
+				trie.insert(sequence, index)
+			},
+			Operation::Delete(index) => {
+				if index >= trie.len() {
+					return Err("The index is out of range.");
+				}
+				trie.delete(index);
+				Ok(())
+			},
+			Operation::Set(index, ref sequence) => {
+				if index >= trie.len() {
+					return Err("The index is out of range.");
+				}
+				let old_sequence = trie.access(index);
+				trie.delete(index);
+				if let Err(reason) = trie.insert(sequence, index) {
+					trie.insert(&old_sequence, index).expect("The old sequence does not fit anymore!");
+					return Err(reason);
+				}
+				Ok(())
+			}
+		}
+	}
+
+	// the operation that undoes this one, if it is done on the trie as it is now.
+	// None if the index is out of range, so the operation will be rejected anyway
+	fn inverse(&self, trie: &WaveletTrie) -> Option<Operation> {
+		match *self {
+			Operation::Insert(index, _) if index <= trie.len() => Some(Operation::Delete(index)),
+			Operation::Delete(index) if index < trie.len() => Some(Operation::Insert(index, trie.access(index))),
+			Operation::Set(index, _) if index < trie.len() => Some(Operation::Set(index, trie.access(index))),
+			_ => None
+		}
+	}
+
+	fn encode(&self) -> Vec<u8> {
+		let mut record = Vec::new();
+		let (op, index, sequence) = match *self {
+			Operation::Insert(index, ref sequence) => (OP_INSERT, index, Some(sequence)),
+			Operation::Delete(index) => (OP_DELETE, index, None),
+			Operation::Set(index, ref sequence) => (OP_SET, index, Some(sequence))
+		};
+		record.push(op);
+		record.extend_from_slice(&u64_to_bytes(index));
+		if let Some(sequence) = sequence {
+			record.extend_from_slice(&u64_to_bytes(sequence.len()));
+			record.extend_from_slice(&bits_to_bytes(sequence));
+		}
+		let mut hasher = Hasher::new();
+		hasher.update(&record);
+		record.extend_from_slice(&u32_to_bytes(hasher.finalize()));
+		record
+	}
+
+	// reads the next record of the log
+	fn decode(reader: &mut dyn Read) -> io::Result<Record> {
+		let mut record = Vec::new();
+		if !read_part(reader, 9, &mut record)? {
+			return Ok(Record::End);
+		}
+		let op = record[0];
+		let index = bytes_to_u64(&record[1..9]);
+		let mut nr_bits = 0;
+		if op == OP_INSERT || op == OP_SET {
+			if !read_part(reader, 8, &mut record)? {
+				return Ok(Record::End);
+			}
+			nr_bits = bytes_to_u64(&record[9..17]);
+			if !read_part(reader, nr_bits.div_ceil(8), &mut record)? {
+				return Ok(Record::End);
+			}
+		} else if op != OP_DELETE {
+			return Ok(Record::Damaged(record.len() as u64));
+		}
+		let content_len = record.len();
+		if !read_part(reader, 4, &mut record)? {
+			return Ok(Record::End);
+		}
+		let mut hasher = Hasher::new();
+		hasher.update(&record[..content_len]);
+		if hasher.finalize() != bytes_to_u32(&record[content_len..]) {
+			return Ok(Record::Damaged(record.len() as u64));
+		}
+		let operation = match op {
+			OP_INSERT => Operation::Insert(index, bytes_to_bits(&record[17..content_len], nr_bits)),
+			OP_SET => Operation::Set(index, bytes_to_bits(&record[17..content_len], nr_bits)),
+			_ => Operation::Delete(index)
+		};
+		Ok(Record::Complete(operation, record.len() as u64))
+	}
+}
+
+// appends "len" bytes from the reader to "buffer"; false if the reader ends first
+fn read_part(reader: &mut dyn Read, len: u64, buffer: &mut Vec<u8>) -> io::Result<bool> {
+	let read = Read::take(&mut *reader, len).read_to_end(buffer)?;
+	Ok(read as u64 == len)
+}
+
+/// A wavelet trie that survives crashes without rewriting the whole trie after
+/// every change.
+///
+/// Every `insert`, `delete` and `set` is appended to a log file before it
+/// returns; opening the trie again replays the log on top of the last
+/// snapshot. `checkpoint` writes a new snapshot and starts an empty log.
+/// Queries go to the trie in memory, see `trie`.
+///
+/// The operations are written to the operating system right away, so they
+/// survive a crash of the process. Call `sync` to make them survive a power
+/// failure as well.
+///
+/// # Examples
+///
+/// ```rust
+/// extern crate wavelet_trie;
+///
+/// use wavelet_trie::wavelet_trie::persistent::PersistentWaveletTrie;
+///
+/// let directory = std::env::temp_dir().join("wavelet_trie_persistent_doc_example");
+/// # let _ = std::fs::remove_dir_all(&directory);
+/// {
+///     let mut wt = PersistentWaveletTrie::open(&directory).unwrap();
+///     wt.append_str("Hello world!").unwrap();
+///     wt.append_str("Hello everybody!").unwrap();
+///     wt.checkpoint().unwrap();
+///     wt.set_str(0, "Goodbye!").unwrap();
+/// }
+///
+/// // the snapshot and the log together give the trie back
+/// let wt = PersistentWaveletTrie::open(&directory).unwrap();
+/// assert_eq!("Goodbye!", wt.trie().access_str(0).unwrap());
+/// assert_eq!(vec![1], wt.trie().select_all_str_prefix("Hell"));
+/// # std::fs::remove_dir_all(&directory).unwrap();
+/// ```
+pub struct PersistentWaveletTrie {
+	directory: PathBuf,
+	generation: u64,
+	trie: WaveletTrie,
+	log: File,
+	log_len: u64     // the length of the log up to the last complete record
+}
+
+impl PersistentWaveletTrie {
+
+	// opens the trie in the given directory, or creates an empty one.
+	// an incomplete operation at the end of the log is dropped; a damaged
+	// one before the end is an error, and the log is not changed.
+	pub fn open<P: AsRef<Path>>(directory: P) -> Result<Self, PersistError> {
+		let directory = directory.as_ref().to_path_buf();
+		fs::create_dir_all(&directory)?;
+
+		// the newest snapshot; older files are leftovers of a checkpoint
+		let mut generation = 0;
+		for entry in fs::read_dir(&directory)? {
+			let name = entry?.file_name();
+			if let Some(found) = name.to_str().and_then(|name| parse_generation(name, "snapshot-", ".wt")) {
+				generation = generation.max(found);
+			}
+		}
+		let trie = if generation == 0 {
+			WaveletTrie::new()
+		} else {
+			let mut reader = BufReader::new(File::open(snapshot_path(&directory, generation))?);
+			WaveletTrie::load(&mut reader)?
+		};
+		let mut persistent = PersistentWaveletTrie {
+			log: OpenOptions::new().read(true).write(true).create(true).truncate(false).open(log_path(&directory, generation))?,
+			directory,
+			generation,
+			trie,
+			log_len: 0
+		};
+		persistent.replay()?;
+		persistent.remove_old_files()?;
+		Ok(persistent)
+	}
+
+	// the trie in memory, for queries
+	pub fn trie(&self) -> &WaveletTrie {
+		&self.trie
+	}
+
+	pub fn len(&self) -> u64 {
+		self.trie.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	pub fn append(&mut self, sequence: &DBVec) -> Result<(), PersistError> {
+		let index = self.len();
+		self.insert(sequence, index)
+	}
+
+	pub fn insert(&mut self, sequence: &DBVec, index: u64) -> Result<(), PersistError> {
+		self.perform(Operation::Insert(index, sequence.copy()))
+	}
+
+	pub fn delete(&mut self, index: u64) -> Result<(), PersistError> {
+		self.perform(Operation::Delete(index))
+	}
+
+	// replaces the sequence at the given index
+	pub fn set(&mut self, index: u64, sequence: &DBVec) -> Result<(), PersistError> {
+		self.perform(Operation::Set(index, sequence.copy()))
+	}
+
+	// appends a string, see WaveletTrie::append_str
+	pub fn append_str(&mut self, text: &str) -> Result<(), PersistError> {
+		self.append(&WaveletTrie::text_to_bitvec(text))
+	}
+
+	pub fn insert_str(&mut self, text: &str, index: u64) -> Result<(), PersistError> {
+		self.insert(&WaveletTrie::text_to_bitvec(text), index)
+	}
+
+	pub fn set_str(&mut self, index: u64, text: &str) -> Result<(), PersistError> {
+		self.set(index, &WaveletTrie::text_to_bitvec(text))
+	}
+
+	// forces the logged operations to the disk
+	pub fn sync(&mut self) -> Result<(), PersistError> {
+		self.log.sync_data()?;
+		Ok(())
+	}
+
+	// writes the trie as a new snapshot, and starts an empty log
+	pub fn checkpoint(&mut self) -> Result<(), PersistError> {
+		let generation = self.generation + 1;
+		let snapshot = snapshot_path(&self.directory, generation);
+		let temporary = self.directory.join(format!("snapshot-{}.wt.tmp", generation));
+		{
+			let mut writer = BufWriter::new(File::create(&temporary)?);
+			self.trie.save_with(&mut writer, Encoding::Succinct)?;
+			let file = writer.into_inner().map_err(|err| err.into_error())?;
+			file.sync_all()?;
+		}
+		fs::rename(&temporary, &snapshot)?;
+		sync_directory(&self.directory);
+
+		let mut log = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(log_path(&self.directory, generation))?;
+		log.write_all(&log_header(generation))?;
+		log.sync_all()?;
+		self.log = log;
+		self.log_len = LOG_HEADER_LEN as u64;
+		self.generation = generation;
+		self.remove_old_files()
+	}
+
+	// applies an operation to the trie and logs it. If logging fails, the operation is undone.
+	fn perform(&mut self, operation: Operation) -> Result<(), PersistError> {
+		let inverse = operation.inverse(&self.trie);
+		operation.apply(&mut self.trie).map_err(PersistError::Rejected)?;
+		let record = operation.encode();
+		if let Err(err) = self.log.write_all(&record) {
+			// cut off what was written of the record, so later records are not lost on replay
+			let _ = self.log.set_len(self.log_len).and_then(|_| self.log.seek(SeekFrom::Start(self.log_len)));
+			if let Some(inverse) = inverse {
+				inverse.apply(&mut self.trie).expect("The operation cannot be undone!");
+			}
+			return Err(PersistError::from(err));
+		}
+		self.log_len += record.len() as u64;
+		Ok(())
+	}
+
+	// applies the operations in the log to the snapshot, and cuts off an incomplete or damaged last record
+	fn replay(&mut self) -> Result<(), PersistError> {
+		let file_len = self.log.metadata()?.len();
+		if file_len < LOG_HEADER_LEN as u64 {
+			// new, or the crash came before the header was complete
+			self.log.set_len(0)?;
+			self.log.seek(SeekFrom::Start(0))?;
+			self.log.write_all(&log_header(self.generation))?;
+			self.log_len = LOG_HEADER_LEN as u64;
+			return Ok(());
+		}
+
+		self.log.seek(SeekFrom::Start(0))?;
+		let mut valid_len = LOG_HEADER_LEN as u64;
+		{
+			let mut reader = BufReader::new(&self.log);
+			let mut header = [0u8; LOG_HEADER_LEN];
+			reader.read_exact(&mut header)?;
+			if &header[0..8] != LOG_MAGIC {
+				return Err(PersistError::Format(FormatError::BadMagic));
+			}
+			let version = bytes_to_u16(&header[8..10]);
+			if version == 0 || version > LOG_VERSION {
+				return Err(PersistError::Format(FormatError::UnsupportedVersion(version)));
+			}
+			if bytes_to_u64(&header[16..24]) != self.generation {
+				return Err(PersistError::Format(FormatError::Corrupt("the log does not belong to the snapshot")));
+			}
+			loop {
+				match Operation::decode(&mut reader)? {
+					Record::Complete(operation, record_len) => {
+						if operation.apply(&mut self.trie).is_err() {
+							return Err(PersistError::Format(FormatError::Corrupt("a logged operation does not fit the trie")));
+						}
+						valid_len += record_len;
+					},
+					// a damaged record that runs to the end was torn by a crash; before the end, it is not
+					Record::Damaged(record_len) if valid_len + record_len < file_len => {
+						return Err(PersistError::Format(FormatError::Corrupt("a record in the middle of the log is damaged")));
+					},
+					Record::Damaged(_) | Record::End => break
+				}
+			}
+		}
+		if valid_len < file_len {
+			self.log.set_len(valid_len)?;
+		}
+		self.log.seek(SeekFrom::Start(valid_len))?;
+		self.log_len = valid_len;
+		Ok(())
+	}
+
+	// removes the files of older generations and unfinished snapshots
+	fn remove_old_files(&self) -> Result<(), PersistError> {
+		for entry in fs::read_dir(&self.directory)? {
+			let entry = entry?;
+			let name = entry.file_name();
+			let name = match name.to_str() {
+				Some(name) => name,
+				None => continue
+			};
+			let old = parse_generation(name, "snapshot-", ".wt")
+				.or_else(|| parse_generation(name, "wal-", ".log"))
+				.is_some_and(|generation| generation < self.generation);
+			let unfinished = parse_generation(name, "snapshot-", ".wt.tmp").is_some();
+			if old || unfinished {
+				fs::remove_file(entry.path())?;
+			}
+		}
+		Ok(())
+	}
+}
+
+fn log_header(generation: u64) -> [u8; LOG_HEADER_LEN] {
+	let mut header = [0u8; LOG_HEADER_LEN];
+	header[0..8].copy_from_slice(LOG_MAGIC);
+	header[8..10].copy_from_slice(&u16_to_bytes(LOG_VERSION));
+	header[16..24].copy_from_slice(&u64_to_bytes(generation));
+	header
+}
+
+fn snapshot_path(directory: &Path, generation: u64) -> PathBuf {
+	directory.join(format!("snapshot-{}.wt", generation))
+}
+
+fn log_path(directory: &Path, generation: u64) -> PathBuf {
+	directory.join(format!("wal-{}.log", generation))
+}
+
+// "snapshot-12.wt" with prefix "snapshot-" and suffix ".wt" gives 12
+fn parse_generation(name: &str, prefix: &str, suffix: &str) -> Option<u64> {
+	if name.starts_with(prefix) && name.ends_with(suffix) && name.len() > prefix.len() + suffix.len() {
+		name[prefix.len()..name.len() - suffix.len()].parse().ok()
+	} else {
+		None
+	}
+}
+
+// makes a rename in the directory durable; not every platform can open a directory
+fn sync_directory(directory: &Path) {
+	if let Ok(directory) = File::open(directory) {
+		let _ = directory.sync_all();
+	}
+}
@@ -0,0 +1,537 @@
+extern crate dyn_bit_vec;
+
+use self::dyn_bit_vec::DBVec;
+use std::borrow::Cow;
+use std::cmp;
+use std::fmt;
+use wavelet_trie::file_format::bits_to_bytes;
+
+// a run costs two words in a run-length vector
+const BITS_PER_RUN: u64 = 128;
+
+// for fewer numbers than one per this many bits, select_sorted does a select per
+// number instead of one pass over all bits
+pub(crate) const SELECTS_PER_PASS: u64 = 64;
+
+// a plain vector of at least this length is checked for runs whenever
+// its length reaches a power of two
+const MIN_CHECK_LEN: u64 = 1024;
+
+// the positions (β) of a node. Highly repetitive positions, e.g. long runs of
+// the same log level, are stored run-length encoded; other positions as a
+// plain bitvector. The representation is chosen per node from the number of
+// runs, and changes when the number of runs changes enough:
+// run-length when the runs take at most half the bits of a plain vector,
+// plain again when they take more bits than a plain vector.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) enum Positions {
+	Plain(DBVec),
+	RunLength(RunLengthBits)
+}
+
+impl Positions {
+
+	pub fn new() -> Self {
+		Positions::Plain(DBVec::new())
+	}
+
+	pub fn from_elem(len: u64, bit: bool) -> Self {
+		Positions::RunLength(RunLengthBits::from_elem(len, bit)).choose()
+	}
+
+	// takes the bits, in the representation that fits them best
+	pub fn from_dbvec(bits: DBVec) -> Self {
+		Positions::Plain(bits).choose()
+	}
+
+	// only the tests look at the representation
+	#[cfg(test)]
+	pub fn is_run_length(&self) -> bool {
+		match *self {
+			Positions::Plain(_) => false,
+			Positions::RunLength(_) => true
+		}
+	}
+
+	// the positions as a plain bitvector; only copied when they are run-length encoded
+	pub fn bits<'a>(&'a self) -> Cow<'a, DBVec> {
+		match *self {
+			Positions::Plain(ref bits) => Cow::Borrowed(bits),
+			Positions::RunLength(ref runs) => Cow::Owned(runs.to_dbvec())
+		}
+	}
+
+	pub fn len(&self) -> u64 {
+		match *self {
+			Positions::Plain(ref bits) => bits.len(),
+			Positions::RunLength(ref runs) => runs.len()
+		}
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	pub fn get(&self, index: u64) -> bool {
+		match *self {
+			Positions::Plain(ref bits) => bits.get(index),
+			Positions::RunLength(ref runs) => runs.get(index)
+		}
+	}
+
+	pub fn rank(&self, bit: bool, index: u64) -> u64 {
+		match *self {
+			Positions::Plain(ref bits) => bits.rank(bit, index),
+			Positions::RunLength(ref runs) => runs.rank(bit, index)
+		}
+	}
+
+	pub fn select(&self, bit: bool, nr: u64) -> Option<u64> {
+		match *self {
+			Positions::Plain(ref bits) => bits.select(bit, nr),
+			Positions::RunLength(ref runs) => runs.select(bit, nr)
+		}
+	}
+
+	pub fn push(&mut self, bit: bool) {
+		let len = self.len();
+		self.insert(bit, len);
+	}
+
+	// replaces the sorted occurrence numbers (from 0) of "bit" by their indices.
+	// Many numbers are found in one pass over the bits instead of a select each
+	pub fn select_sorted(&self, bit: bool, nrs: &mut [u64]) {
+		match *self {
+			Positions::Plain(ref bits) if nrs.len() as u64 * SELECTS_PER_PASS >= bits.len() => {
+				let bytes = bits_to_bytes(bits);
+				let last_bits = bits.len() % 8;
+				let mut next = 0;   // the first number that is not found yet
+				let mut seen = 0;   // the number of "bit" before the current byte
+				for (byte_nr, &byte) in bytes.iter().enumerate() {
+					if next == nrs.len() {
+						break;
+					}
+					let mut byte = if bit { byte } else { !byte };
+					if byte_nr == bytes.len() - 1 && last_bits != 0 {
+						byte &= (1u8 << last_bits) - 1;
+					}
+					let count = byte.count_ones() as u64;
+					while next < nrs.len() && nrs[next] < seen + count {
+						nrs[next] = byte_nr as u64 * 8 + nth_one(byte, nrs[next] - seen);
+						next += 1;
+					}
+					seen += count;
+				}
+				assert!(next == nrs.len(), "This cannot happen!");
+			},
+			Positions::RunLength(ref runs) => runs.select_sorted(bit, nrs),
+			_ => {
+				for nr in nrs.iter_mut() {
+					*nr = self.select(bit, *nr + 1).expect("This cannot happen!");
+				}
+			}
+		}
+	}
+
+	// adds "count" times "bit" at the end; the representation is checked once
+	pub fn push_run(&mut self, bit: bool, count: u64) {
+		let old_len = self.len();
+		let check = match *self {
+			Positions::Plain(ref mut bits) => {
+				bits.append_vec(&mut DBVec::from_elem(count, bit));
+				passes_check_len(old_len, bits.len())
+			},
+			Positions::RunLength(ref mut runs) => {
+				runs.push_run(bit, count);
+				runs.nr_runs() * BITS_PER_RUN > runs.len()
+			}
+		};
+		if check {
+			self.rechoose();
+		}
+	}
+
+	// adds the bits at the end; the representation is checked once
+	pub fn append(&mut self, mut bits: DBVec) {
+		let old_len = self.len();
+		let check = match *self {
+			Positions::Plain(ref mut plain) => {
+				plain.append_vec(&mut bits);
+				passes_check_len(old_len, plain.len())
+			},
+			Positions::RunLength(ref mut runs) => {
+				let mut start = 0;
+				for index in 1..bits.len() + 1 {
+					if index == bits.len() || bits.get(index) != bits.get(start) {
+						runs.push_run(bits.get(start), index - start);
+						start = index;
+					}
+				}
+				runs.nr_runs() * BITS_PER_RUN > runs.len()
+			}
+		};
+		if check {
+			self.rechoose();
+		}
+	}
+
+	pub fn insert(&mut self, bit: bool, index: u64) {
+		let check = match *self {
+			Positions::Plain(ref mut bits) => {
+				bits.insert(bit, index);
+				bits.len() >= MIN_CHECK_LEN && bits.len().is_power_of_two()
+			},
+			Positions::RunLength(ref mut runs) => {
+				runs.insert(bit, index);
+				runs.nr_runs() * BITS_PER_RUN > runs.len()
+			}
+		};
+		if check {
+			self.rechoose();
+		}
+	}
+
+	pub fn delete(&mut self, index: u64) {
+		let check = match *self {
+			Positions::Plain(ref mut bits) => {
+				bits.delete(index);
+				false
+			},
+			Positions::RunLength(ref mut runs) => {
+				runs.delete(index);
+				runs.nr_runs() * BITS_PER_RUN > runs.len()
+			}
+		};
+		if check {
+			self.rechoose();
+		}
+	}
+
+	// sets all positions to 0
+	pub fn set_none(&mut self) {
+		let len = self.len();
+		*self = Positions::from_elem(len, false);
+	}
+
+	pub fn sparseness(&self) -> f64 {
+		self.bits().sparseness()
+	}
+
+	pub fn allocated_bytes(&self) -> usize {
+		match *self {
+			Positions::Plain(ref bits) => bits.allocated_bytes(),
+			Positions::RunLength(ref runs) => runs.allocated_bytes()
+		}
+	}
+
+	// gives back the memory that is allocated but not used
+	pub fn shrink_to_fit(&mut self) {
+		match *self {
+			Positions::Plain(ref mut bits) => shrink_bits(bits),
+			Positions::RunLength(ref mut runs) => runs.shrink_to_fit()
+		}
+	}
+
+	fn rechoose(&mut self) {
+		let positions = ::std::mem::replace(self, Positions::new());
+		*self = positions.choose();
+	}
+
+	fn choose(self) -> Self {
+		match self {
+			Positions::Plain(bits) => {
+				let runs = RunLengthBits::from_dbvec(&bits);
+				if runs.nr_runs() * BITS_PER_RUN * 2 <= runs.len() {
+					Positions::RunLength(runs)
+				} else {
+					Positions::Plain(bits)
+				}
+			},
+			Positions::RunLength(runs) => {
+				if runs.nr_runs() * BITS_PER_RUN > runs.len() {
+					Positions::Plain(runs.to_dbvec())
+				} else {
+					Positions::RunLength(runs)
+				}
+			}
+		}
+	}
+}
+
+// replaces "bits" by a copy if that takes less memory: DBVec cannot give back
+// memory itself, but a copy only allocates what it needs
+pub(crate) fn shrink_bits(bits: &mut DBVec) {
+	let copy = bits.copy();
+	if copy.allocated_bytes() < bits.allocated_bytes() {
+		*bits = copy;
+	}
+}
+
+// the index of the 1 after "nr" other 1s in "byte", from the least significant bit on
+fn nth_one(byte: u8, nr: u64) -> u64 {
+	let mut byte = byte;
+	for _ in 0..nr {
+		byte &= byte - 1;
+	}
+	byte.trailing_zeros() as u64
+}
+
+// whether a plain vector that grows from old_len to new_len reaches a length
+// where it is checked for runs, like insert checks it
+fn passes_check_len(old_len: u64, new_len: u64) -> bool {
+	let next_check = cmp::max(MIN_CHECK_LEN, (old_len + 1).next_power_of_two());
+	next_check <= new_len
+}
+
+impl fmt::Debug for Positions {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Positions::Plain(ref bits) => write!(f, "{:?}", bits),
+			Positions::RunLength(ref runs) => write!(f, "{:?} (run-length)", runs.to_dbvec())
+		}
+	}
+}
+
+// a bitvector as a list of runs of equal bits. The runs alternate between
+// 0s and 1s, so only the bit of the first run is stored.
+// The cumulative ends and numbers of 1s make get, rank and select a binary
+// search; insert and delete shift the runs after the change.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct RunLengthBits {
+	first_bit: bool,
+	ends: Vec<u64>,   // the end (exclusive) of every run
+	ones: Vec<u64>    // the number of 1s up to the end of every run
+}
+
+impl RunLengthBits {
+
+	pub fn from_elem(len: u64, bit: bool) -> Self {
+		let mut runs = RunLengthBits {
+			first_bit: bit,
+			ends: Vec::new(),
+			ones: Vec::new()
+		};
+		if len > 0 {
+			runs.ends.push(len);
+			runs.ones.push(if bit { len } else { 0 });
+		}
+		runs
+	}
+
+	pub fn from_dbvec(bits: &DBVec) -> Self {
+		let mut runs = RunLengthBits::from_elem(0, false);
+		// to_bytes puts bit i in bit i % 8 of byte i / 8
+		let bytes = bits.to_bytes();
+		for index in 0..bits.len() {
+			let bit = (bytes[(index / 8) as usize] >> (index % 8)) & 1 == 1;
+			runs.push(bit);
+		}
+		runs
+	}
+
+	pub fn to_dbvec(&self) -> DBVec {
+		let mut bits = DBVec::new();
+		let mut start = 0;
+		for (run_nr, end) in self.ends.iter().enumerate() {
+			bits.append_vec(&mut DBVec::from_elem(end - start, self.run_bit(run_nr)));
+			start = *end;
+		}
+		bits
+	}
+
+	pub fn len(&self) -> u64 {
+		self.ends.last().cloned().unwrap_or(0)
+	}
+
+	pub fn nr_runs(&self) -> u64 {
+		self.ends.len() as u64
+	}
+
+	pub fn allocated_bytes(&self) -> usize {
+		(self.ends.capacity() + self.ones.capacity()) * 8
+	}
+
+	pub fn shrink_to_fit(&mut self) {
+		self.ends.shrink_to_fit();
+		self.ones.shrink_to_fit();
+	}
+
+	fn run_bit(&self, run_nr: usize) -> bool {
+		self.first_bit ^ (run_nr % 2 == 1)
+	}
+
+	fn run_start(&self, run_nr: usize) -> u64 {
+		if run_nr == 0 { 0 } else { self.ends[run_nr - 1] }
+	}
+
+	fn ones_before(&self, run_nr: usize) -> u64 {
+		if run_nr == 0 { 0 } else { self.ones[run_nr - 1] }
+	}
+
+	// the number of the run holding the given index
+	fn run_of(&self, index: u64) -> usize {
+		match self.ends.binary_search(&index) {
+			Ok(run_nr) => run_nr + 1,   // index is the first bit after run_nr
+			Err(run_nr) => run_nr
+		}
+	}
+
+	pub fn get(&self, index: u64) -> bool {
+		self.run_bit(self.run_of(index))
+	}
+
+	pub fn rank(&self, bit: bool, index: u64) -> u64 {
+		let ones = if index == 0 {
+			0
+		} else {
+			let run_nr = self.run_of(index - 1);
+			let in_run = if self.run_bit(run_nr) { index - self.run_start(run_nr) } else { 0 };
+			self.ones_before(run_nr) + in_run
+		};
+		if bit { ones } else { index - ones }
+	}
+
+	// the position of the nr-th "bit"; nr starts at 1. None if there is no such bit.
+	pub fn select(&self, bit: bool, nr: u64) -> Option<u64> {
+		let count_up_to = |run_nr: usize| {
+			if bit { self.ones[run_nr] } else { self.ends[run_nr] - self.ones[run_nr] }
+		};
+		if nr == 0 || self.ends.is_empty() || count_up_to(self.ends.len() - 1) < nr {
+			return None;
+		}
+		// the first run up to which there are nr "bit"s
+		let (mut low, mut high) = (0, self.ends.len() - 1);
+		while low < high {
+			let middle = (low + high) / 2;
+			if count_up_to(middle) < nr {
+				low = middle + 1;
+			} else {
+				high = middle;
+			}
+		}
+		let before = if low == 0 { 0 } else { count_up_to(low - 1) };
+		Some(self.run_start(low) + nr - before - 1)
+	}
+
+	pub fn push(&mut self, bit: bool) {
+		let len = self.len();
+		self.insert(bit, len);
+	}
+
+	// like Positions::select_sorted, one run at a time
+	pub fn select_sorted(&self, bit: bool, nrs: &mut [u64]) {
+		let mut next = 0;
+		for run_nr in 0..self.ends.len() {
+			if next == nrs.len() {
+				break;
+			}
+			if self.run_bit(run_nr) != bit {
+				continue;
+			}
+			let start = self.run_start(run_nr);
+			let ones_before = self.ones_before(run_nr);
+			let seen = if bit { ones_before } else { start - ones_before };
+			while next < nrs.len() && nrs[next] < seen + self.ends[run_nr] - start {
+				nrs[next] = start + nrs[next] - seen;
+				next += 1;
+			}
+		}
+		assert!(next == nrs.len(), "This cannot happen!");
+	}
+
+	// adds "count" times "bit" at the end
+	pub fn push_run(&mut self, bit: bool, count: u64) {
+		if count == 0 {
+			return;
+		}
+		let ones = if bit { count } else { 0 };
+		match self.ends.len() {
+			0 => *self = RunLengthBits::from_elem(count, bit),
+			nr_runs if self.run_bit(nr_runs - 1) == bit => {
+				self.ends[nr_runs - 1] += count;
+				self.ones[nr_runs - 1] += ones;
+			},
+			nr_runs => {
+				let (end, ones_before) = (self.ends[nr_runs - 1], self.ones[nr_runs - 1]);
+				self.ends.push(end + count);
+				self.ones.push(ones_before + ones);
+			}
+		}
+	}
+
+	pub fn insert(&mut self, bit: bool, index: u64) {
+		if self.ends.is_empty() {
+			*self = RunLengthBits::from_elem(1, bit);
+			return;
+		}
+		let len = self.len();
+		let run_nr = if index == len { self.ends.len() - 1 } else { self.run_of(index) };
+		let start = self.run_start(run_nr);
+		if self.run_bit(run_nr) == bit {
+			// grow the run
+			self.shift(run_nr, bit, true);
+		} else if index == start && run_nr > 0 {
+			// grow the run before, which has the same bit
+			self.shift(run_nr - 1, bit, true);
+		} else {
+			// split the run, with the new bit as a run in between
+			let run_bit = !bit;
+			let end = self.ends[run_nr];
+			let mut ones = self.ones_before(run_nr);
+			let mut new_ends = Vec::with_capacity(3);
+			let mut new_ones = Vec::with_capacity(3);
+			if index > start {
+				ones += if run_bit { index - start } else { 0 };
+				new_ends.push(index);
+				new_ones.push(ones);
+			}
+			ones += bit as u64;
+			new_ends.push(index + 1);
+			new_ones.push(ones);
+			if index < end {
+				ones += if run_bit { end - index } else { 0 };
+				new_ends.push(end + 1);
+				new_ones.push(ones);
+			}
+			if index == start {
+				self.first_bit = bit;
+			}
+			self.ends.splice(run_nr..run_nr + 1, new_ends.iter().cloned());
+			self.ones.splice(run_nr..run_nr + 1, new_ones.iter().cloned());
+			let after = run_nr + new_ends.len();
+			self.shift(after, bit, true);
+		}
+	}
+
+	pub fn delete(&mut self, index: u64) {
+		let run_nr = self.run_of(index);
+		let bit = self.run_bit(run_nr);
+		if self.ends[run_nr] - self.run_start(run_nr) > 1 {
+			self.shift(run_nr, bit, false);
+		} else {
+			// the run disappears, and the runs around it merge
+			self.ends.remove(run_nr);
+			self.ones.remove(run_nr);
+			self.shift(run_nr, bit, false);
+			if run_nr == 0 {
+				self.first_bit = !bit;
+			} else if run_nr < self.ends.len() {
+				self.ends.remove(run_nr - 1);
+				self.ones.remove(run_nr - 1);
+			}
+		}
+	}
+
+	// adds (or removes) one "bit" to the runs from run_nr on
+	fn shift(&mut self, run_nr: usize, bit: bool, add: bool) {
+		for (end, ones) in self.ends[run_nr..].iter_mut().zip(self.ones[run_nr..].iter_mut()) {
+			if add {
+				*end += 1;
+				*ones += bit as u64;
+			} else {
+				*end -= 1;
+				*ones -= bit as u64;
+			}
+		}
+	}
+}
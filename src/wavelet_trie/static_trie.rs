@@ -0,0 +1,427 @@
+extern crate dyn_bit_vec;
+
+use self::dyn_bit_vec::DBVec;
+use wavelet_trie::{WaveletTrie, Node, DecodeError, push_node};
+use wavelet_trie::positions::Positions;
+use wavelet_trie::file_format::bits_to_bytes;
+
+const BLOCK_BITS: u64 = 512;
+const WORDS_PER_BLOCK: u64 = BLOCK_BITS / 64;
+const SELECT_SAMPLE_RATE: u64 = 4096;
+
+// a plain, immutable bitvector. Bit i is bit i % 64 of word i / 64.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct PlainBits {
+	words: Vec<u64>,
+	len: u64
+}
+
+impl PlainBits {
+	fn from_dbvec(bits: &DBVec) -> Self {
+		// bit i is in bit i % 8 of byte i / 8, so the bytes are the little endian words.
+		// the bits after the end are 0, so popcounts of whole words are right
+		let bytes = bits_to_bytes(bits);
+		let words = bytes.chunks(8).map(|chunk| {
+			chunk.iter().enumerate().fold(0u64, |word, (byte_nr, byte)| word | (*byte as u64) << (byte_nr * 8))
+		}).collect();
+		PlainBits {
+			words,
+			len: bits.len()
+		}
+	}
+
+	fn to_dbvec(&self) -> DBVec {
+		let full_bytes = (self.len / 8) as usize;
+		let bytes: Vec<u8> = self.words.iter()
+			.flat_map(|word| (0..8).map(move |byte_nr| (word >> (byte_nr * 8)) as u8))
+			.take(full_bytes)
+			.collect();
+		let mut bits = DBVec::from_bytes(&bytes);
+		for bit_nr in full_bytes as u64 * 8..self.len {
+			bits.push(self.get(bit_nr));
+		}
+		bits
+	}
+
+	fn get(&self, bit_nr: u64) -> bool {
+		(self.words[(bit_nr / 64) as usize] >> (bit_nr % 64)) & 1 == 1
+	}
+
+	// true if bits start up to start + len - 1 of sequence are the first len bits of this vector
+	fn matches(&self, sequence: &DBVec, start: u64, len: u64) -> bool {
+		(0..len).all(|bit_nr| self.get(bit_nr) == sequence.get(start + bit_nr))
+	}
+}
+
+// a plain bitvector with a rank directory and select samples:
+// rank takes at most WORDS_PER_BLOCK popcounts, select a binary search
+// between two samples and a scan of one block.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct RankSelectBits {
+	bits: PlainBits,
+	blocks: Vec<u64>,        // the number of 1s before every block of BLOCK_BITS bits, and the total
+	one_samples: Vec<u64>,   // the block holding the (k * SELECT_SAMPLE_RATE + 1)-th 1
+	zero_samples: Vec<u64>   // the same for 0s
+}
+
+impl RankSelectBits {
+	fn new(bits: PlainBits) -> Self {
+		let nr_blocks = bits.len.div_ceil(BLOCK_BITS) as usize;
+		let mut blocks = Vec::with_capacity(nr_blocks + 1);
+		let mut one_samples = Vec::new();
+		let mut zero_samples = Vec::new();
+		let mut ones = 0;
+		for block_nr in 0..nr_blocks {
+			blocks.push(ones);
+			let first_word = block_nr * WORDS_PER_BLOCK as usize;
+			let last_word = (first_word + WORDS_PER_BLOCK as usize).min(bits.words.len());
+			ones += bits.words[first_word..last_word].iter().map(|word| word.count_ones() as u64).sum::<u64>();
+			let zeros = ((block_nr as u64 + 1) * BLOCK_BITS).min(bits.len) - ones;
+			while one_samples.len() as u64 * SELECT_SAMPLE_RATE < ones {
+				one_samples.push(block_nr as u64);
+			}
+			while zero_samples.len() as u64 * SELECT_SAMPLE_RATE < zeros {
+				zero_samples.push(block_nr as u64);
+			}
+		}
+		blocks.push(ones);
+		RankSelectBits {
+			bits,
+			blocks,
+			one_samples,
+			zero_samples
+		}
+	}
+
+	fn len(&self) -> u64 {
+		self.bits.len
+	}
+
+	fn get(&self, bit_nr: u64) -> bool {
+		self.bits.get(bit_nr)
+	}
+
+	// the number of "bit"s before position pos
+	fn rank(&self, bit: bool, pos: u64) -> u64 {
+		let block_nr = pos / BLOCK_BITS;
+		let mut ones = self.blocks[block_nr as usize];
+		for word_nr in block_nr * WORDS_PER_BLOCK..pos / 64 {
+			ones += self.bits.words[word_nr as usize].count_ones() as u64;
+		}
+		if !pos.is_multiple_of(64) {
+			let mask = (1u64 << (pos % 64)) - 1;
+			ones += (self.bits.words[(pos / 64) as usize] & mask).count_ones() as u64;
+		}
+		if bit { ones } else { pos - ones }
+	}
+
+	// the position of the nr-th "bit"; nr starts at 1. None if there is no such bit.
+	fn select(&self, bit: bool, nr: u64) -> Option<u64> {
+		let total = if bit { self.blocks[self.blocks.len() - 1] } else { self.len() - self.blocks[self.blocks.len() - 1] };
+		if nr == 0 || nr > total {
+			return None;
+		}
+		let count_before = |block_nr: u64| {
+			let ones = self.blocks[block_nr as usize];
+			if bit { ones } else { block_nr * BLOCK_BITS - ones }
+		};
+
+		// the samples narrow down the blocks; a binary search finds the block
+		let samples = if bit { &self.one_samples } else { &self.zero_samples };
+		let sample_nr = ((nr - 1) / SELECT_SAMPLE_RATE) as usize;
+		let mut low = samples[sample_nr];
+		let mut high = match samples.get(sample_nr + 1) {
+			Some(block_nr) => *block_nr,
+			None => self.blocks.len() as u64 - 2
+		};
+		while low < high {
+			let middle = (low + high).div_ceil(2);
+			if count_before(middle) < nr {
+				low = middle;
+			} else {
+				high = middle - 1;
+			}
+		}
+
+		// scan the words of the block
+		let mut left = nr - count_before(low);
+		let mut word_nr = low * WORDS_PER_BLOCK;
+		loop {
+			let word = self.bits.words[word_nr as usize];
+			let word = if bit { word } else { !word };
+			let count = word.count_ones() as u64;
+			if count >= left {
+				let mut word = word;
+				for _ in 1..left {
+					word &= word - 1;
+				}
+				return Some(word_nr * 64 + word.trailing_zeros() as u64);
+			}
+			left -= count;
+			word_nr += 1;
+		}
+	}
+}
+
+/// An immutable wavelet trie, for indexes that do not change after they are built.
+///
+/// A `StaticWaveletTrie` is made from a `WaveletTrie` with `freeze`, and can be
+/// turned back into one with `thaw`. It has the same queries, but its bitvectors
+/// are plain arrays of words with a rank directory and select samples, so `rank`,
+/// `select` and `access` take constant time per node instead of walking the
+/// structure of a dynamic bitvector.
+///
+/// # Examples
+///
+/// ```rust
+/// extern crate wavelet_trie;
+///
+/// use wavelet_trie::wavelet_trie::WaveletTrie;
+///
+/// let mut wt = WaveletTrie::new();
+/// wt.append_str("Hello world!").unwrap();
+/// wt.append_str("Hello everybody!").unwrap();
+/// wt.append_str("Goodbye!").unwrap();
+///
+/// let frozen = wt.freeze();
+/// assert_eq!(vec![0, 1], frozen.select_all_str_prefix("Hell"));
+/// assert_eq!(Some(1), frozen.rank_str_exact("Hello world!", 3));
+/// assert_eq!("Goodbye!", frozen.access_str(2).unwrap());
+///
+/// // make changes again
+/// let mut wt = frozen.thaw();
+/// wt.append_str("Hello again!").unwrap();
+/// assert_eq!(3, wt.select_all_str_prefix("Hell").len());
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StaticWaveletTrie {
+	nodes: Vec<StaticNode>   // in level order, so the root is node 0
+}
+
+// a node of a frozen trie
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct StaticNode {
+	prefix: PlainBits,
+	len: u64,                        // the number of sequences in this subtrie
+	positions: RankSelectBits,       // empty in a leaf
+	children: Option<(u32, u32)>     // the indices of the left and right subtrie, if any
+}
+
+impl WaveletTrie {
+
+	// makes an immutable copy of the trie with faster queries
+	pub fn freeze(&self) -> StaticWaveletTrie {
+		// in level order the children of the inner nodes follow each other, two by two
+		let mut next_child = 1;
+		let nodes = self.level_order().into_iter().map(|node| {
+			let (positions, children) = match node.children {
+				Some(_) => {
+					next_child += 2;
+					(PlainBits::from_dbvec(&node.positions.bits()), Some((next_child - 2, next_child - 1)))
+				},
+				None => (PlainBits { words: Vec::new(), len: 0 }, None)
+			};
+			StaticNode {
+				prefix: PlainBits::from_dbvec(&node.prefix),
+				len: node.positions.len(),
+				positions: RankSelectBits::new(positions),
+				children
+			}
+		}).collect();
+		StaticWaveletTrie {
+			nodes
+		}
+	}
+}
+
+impl StaticNode {
+
+	fn is_leaf(&self) -> bool {
+		self.children.is_none()
+	}
+
+	// the index of the left (false) or right (true) child
+	fn child(&self, bit: bool) -> Option<u32> {
+		self.children.map(|(left, right)| if bit { right } else { left })
+	}
+
+	// rank on the positions; the positions of a leaf are all 0
+	fn positions_rank(&self, bit: bool, index: u64) -> u64 {
+		match (self.is_leaf(), bit) {
+			(true, true) => 0,
+			(true, false) => index,
+			(false, _) => self.positions.rank(bit, index)
+		}
+	}
+}
+
+impl StaticWaveletTrie {
+
+	// makes a dynamic copy of the trie, that can be changed again.
+	// The nodes keep their indices.
+	pub fn thaw(&self) -> WaveletTrie {
+		let mut nodes = Vec::with_capacity(self.nodes.len());
+		for node in &self.nodes {
+			let prefix = node.prefix.to_dbvec();
+			push_node(&mut nodes, match node.children {
+				Some(children) => Node {
+					prefix,
+					positions: Positions::from_dbvec(node.positions.bits.to_dbvec()),
+					children: Some(children)
+				},
+				None => Node::leaf(prefix, Positions::from_elem(node.len, false))
+			});
+		}
+		WaveletTrie::from_nodes(nodes, 0)
+	}
+
+	pub fn len(&self) -> u64 {
+		self.root().len
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	fn root(&self) -> &StaticNode {
+		&self.nodes[0]
+	}
+
+	fn node(&self, id: u32) -> &StaticNode {
+		&self.nodes[id as usize]
+	}
+
+	// retrieve the sequence at the given index
+	pub fn access(&self, index: u64) -> DBVec {
+		let mut result = DBVec::new();
+		let mut node = self.root();
+		let mut index = index;
+		loop {
+			for bit_nr in 0..node.prefix.len {
+				result.push(node.prefix.get(bit_nr));
+			}
+			let child = match node.children {
+				Some(_) => {
+					let bit = node.positions.get(index);
+					index = node.positions.rank(bit, index);
+					result.push(bit);
+					node.child(bit).unwrap()
+				},
+				None => return result
+			};
+			node = self.node(child);
+		}
+	}
+
+	// counts the number of occurrences "sequence" (can be a prefix) up to index − 1.
+	// returns None if sequence does not occur
+	pub fn rank(&self, sequence: &DBVec, index: u64) -> Option<u64> {
+		let mut node = self.root();
+		if node.prefix.len == 0 && node.len == 0 {
+			return None;
+		}
+		let mut start = 0;
+		let mut index = index;
+		loop {
+			let remaining = sequence.len() - start;
+			if remaining <= node.prefix.len {
+				// sequence has to be a prefix of "prefix"
+				return if node.prefix.matches(sequence, start, remaining) { Some(index) } else { None };
+			} else if !node.prefix.matches(sequence, start, node.prefix.len) {
+				return None;
+			}
+			let bit = sequence.get(start + node.prefix.len);
+			index = node.positions_rank(bit, index);
+			start += node.prefix.len + 1;
+			node = match node.child(bit) {
+				Some(child) => self.node(child),
+				None => return Some(index)
+			};
+		}
+	}
+
+	// the nodes and bits on the way to the node where "sequence" matches, and that node.
+	// None if sequence is not in the trie.
+	fn find_path(&self, sequence: &DBVec) -> Option<(Vec<(&StaticNode, bool)>, &StaticNode)> {
+		let mut path = Vec::new();
+		let mut node = self.root();
+		let mut start = 0;
+		loop {
+			let remaining = sequence.len() - start;
+			if remaining <= node.prefix.len {
+				return if node.prefix.matches(sequence, start, remaining) { Some((path, node)) } else { None };
+			} else if node.is_leaf() || !node.prefix.matches(sequence, start, node.prefix.len) {
+				return None;
+			}
+			let bit = sequence.get(start + node.prefix.len);
+			path.push((node, bit));
+			start += node.prefix.len + 1;
+			node = self.node(node.child(bit).unwrap());
+		}
+	}
+
+	// find the position of the occurrence_nr-th given sequence (can be a prefix)
+	// an occurrence number starts at 1 (a zero-th occurrence makes no sense)
+	// returns None if not found.
+	pub fn select(&self, sequence: &DBVec, occurrence_nr: u64) -> Option<u64> {
+		if occurrence_nr == 0 {
+			return None;
+		}
+		let (path, _) = self.find_path(sequence)?;
+		let mut position = occurrence_nr - 1;
+		for &(node, bit) in path.iter().rev() {
+			position = node.positions.select(bit, position + 1)?;
+		}
+		Some(position)
+	}
+
+	// find the positions of all occurrences of the given sequence (can be prefix)
+	pub fn select_all(&self, sequence: &DBVec) -> Vec<u64> {
+		let (path, found) = match self.find_path(sequence) {
+			Some(found) => found,
+			None => return Vec::new()
+		};
+		let mut all_positions: Vec<u64> = (0..found.len).collect();
+		for &(node, bit) in path.iter().rev() {
+			for position in all_positions.iter_mut() {
+				*position = node.positions.select(bit, *position + 1).expect("This cannot happen!");
+			}
+		}
+		all_positions
+	}
+
+	// retrieves the string at the given index, see WaveletTrie::access_str
+	pub fn access_str(&self, index: u64) -> Result<String, DecodeError> {
+		WaveletTrie::bitvec_to_text(&self.access(index))
+	}
+
+	// counts the number of occurrences of exactly "text" up to index - 1.
+	pub fn rank_str_exact(&self, text: &str, index: u64) -> Option<u64> {
+		self.rank(&WaveletTrie::text_to_bitvec(text), index)
+	}
+
+	// counts the number of strings starting with "text" up to index - 1.
+	pub fn rank_str_prefix(&self, text: &str, index: u64) -> Option<u64> {
+		self.rank(&WaveletTrie::prefix_to_bitvec(text), index)
+	}
+
+	// finds the position of the occurrence_nr-th occurrence of exactly "text"
+	pub fn select_str_exact(&self, text: &str, occurrence_nr: u64) -> Option<u64> {
+		self.select(&WaveletTrie::text_to_bitvec(text), occurrence_nr)
+	}
+
+	// finds the position of the occurrence_nr-th string starting with "text"
+	pub fn select_str_prefix(&self, text: &str, occurrence_nr: u64) -> Option<u64> {
+		self.select(&WaveletTrie::prefix_to_bitvec(text), occurrence_nr)
+	}
+
+	// finds the positions of all occurrences of exactly "text"
+	pub fn select_all_str_exact(&self, text: &str) -> Vec<u64> {
+		self.select_all(&WaveletTrie::text_to_bitvec(text))
+	}
+
+	// finds the positions of all strings starting with "text"
+	pub fn select_all_str_prefix(&self, text: &str) -> Vec<u64> {
+		self.select_all(&WaveletTrie::prefix_to_bitvec(text))
+	}
+}
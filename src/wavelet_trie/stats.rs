@@ -0,0 +1,76 @@
+// Statistics about the shape and the memory use of a trie, for capacity planning.
+// The heap size counts what is really allocated: the arena of nodes with its
+// unused capacity, the list of free places, and the prefix and positions of
+// every node, so it can be larger than the number of bits in use.
+
+use std::cmp;
+use std::mem;
+use wavelet_trie::{WaveletTrie, Node};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TrieStats {
+	pub nr_nodes: u64,          // all nodes, the root included
+	pub nr_leaves: u64,         // the number of distinct sequences
+	pub max_depth: u64,         // the number of edges from the root to the deepest leaf
+	pub average_depth: f64,     // the depth of the leaves, on average
+	pub prefix_bits: u64,       // the bits in the prefixes of all nodes
+	pub position_bits: u64,     // the bits in the positions of all nodes
+	pub heap_bytes: usize,      // the bytes allocated on the heap, see heap_size
+	pub bits_per_element: f64   // heap_bytes in bits per sequence in the trie
+}
+
+impl WaveletTrie {
+
+	// gathers the statistics of the trie in one walk over its nodes
+	pub fn stats(&self) -> TrieStats {
+		let mut stats = TrieStats {
+			nr_nodes: 0,
+			nr_leaves: 0,
+			max_depth: 0,
+			average_depth: 0.0,
+			prefix_bits: 0,
+			position_bits: 0,
+			heap_bytes: self.heap_size(),
+			bits_per_element: 0.0
+		};
+		if self.len() == 0 {
+			return stats;
+		}
+		let mut total_depth = 0;
+		let mut stack = vec![(self.root_node(), 0)];
+		while let Some((node, depth)) = stack.pop() {
+			stats.nr_nodes += 1;
+			stats.prefix_bits += node.prefix.len();
+			stats.position_bits += node.positions.len();
+			match node.children {
+				Some((left, right)) => {
+					stack.push((self.node(right), depth + 1));
+					stack.push((self.node(left), depth + 1));
+				},
+				None => {
+					stats.nr_leaves += 1;
+					stats.max_depth = cmp::max(stats.max_depth, depth);
+					total_depth += depth;
+				}
+			}
+		}
+		stats.average_depth = total_depth as f64 / stats.nr_leaves as f64;
+		stats.bits_per_element = (stats.heap_bytes * 8) as f64 / self.len() as f64;
+		stats
+	}
+
+	// the number of bytes the trie has allocated on the heap, including the
+	// places in the arena that are free or not used yet
+	pub fn heap_size(&self) -> usize {
+		// a free place holds an empty node, which adds nothing
+		let arena_bytes = self.nodes.capacity() * mem::size_of::<Node>() + self.free.capacity() * mem::size_of::<u32>();
+		arena_bytes + self.nodes.iter().map(Node::heap_size).sum::<usize>()
+	}
+}
+
+impl Node {
+	// the bytes of the prefix and positions; the node itself is in the arena
+	fn heap_size(&self) -> usize {
+		self.prefix.allocated_bytes() + self.positions.allocated_bytes()
+	}
+}
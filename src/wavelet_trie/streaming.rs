@@ -0,0 +1,186 @@
+// Streaming versions of save and load: write_to and read_from write and read
+// one node at a time through a buffer of fixed size instead of building the
+// whole file in memory.
+//
+// write_to writes exactly what save writes: the node list encoding of the
+// file format described in file_format.rs, with the same code. read_from
+// reads that encoding node by node; files in the other encodings are read
+// with load.
+
+extern crate crc32fast;
+
+use self::crc32fast::Hasher;
+use std::io;
+use std::io::{BufReader, BufWriter, Read, Write};
+use wavelet_trie::WaveletTrie;
+use wavelet_trie::file_format::{FormatError, ENCODING_NODE_LIST, HEADER_LEN, encode_header, decode_header, u32_to_bytes, bytes_to_u32};
+
+// the size of the buffer between the trie and the writer or reader
+const BUFFER_SIZE: usize = 64 * 1024;
+
+// progress is reported at least every this many bytes, and at the end
+const PROGRESS_INTERVAL: u64 = 1024 * 1024;
+
+// how far writing or reading a trie is
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Progress {
+	pub bytes: u64,         // bytes written or read so far
+	pub total_bytes: u64,   // bytes in the whole file
+	pub nodes: u64,         // nodes written or read so far
+	pub total_nodes: u64    // nodes in the whole trie
+}
+
+impl WaveletTrie {
+
+	// writes the trie like save does, see the top of streaming.rs
+	pub fn write_to<W: Write>(&self, writer: W) -> io::Result<()> {
+		self.write_to_with_progress(writer, |_| {})
+	}
+
+	// like write_to; "progress" is called regularly while writing
+	pub fn write_to_with_progress<W: Write, F: FnMut(Progress)>(&self, writer: W, mut progress: F) -> io::Result<()> {
+		let (payload_len, total_nodes) = self.node_list_len();
+		let header = encode_header(ENCODING_NODE_LIST, self.len(), total_nodes, payload_len);
+		let mut out = HashingWriter::new(writer);
+		out.write_all(&header)?;
+		let mut report = Progress {
+			bytes: HEADER_LEN as u64,
+			total_bytes: HEADER_LEN as u64 + payload_len + 4,
+			nodes: 0,
+			total_nodes
+		};
+		let mut reported_bytes = 0;
+		self.write_node_list(&mut out, Some(&mut |nodes, bytes| {
+			report.nodes = nodes;
+			report.bytes = HEADER_LEN as u64 + bytes;
+			if report.bytes - reported_bytes >= PROGRESS_INTERVAL {
+				progress(report);
+				reported_bytes = report.bytes;
+			}
+		}))?;
+		let checksum = out.hasher.clone().finalize();
+		out.inner.write_all(&u32_to_bytes(checksum))?;
+		out.inner.flush()?;
+		report.bytes = report.total_bytes;
+		progress(report);
+		Ok(())
+	}
+
+	// reads a trie written by write_to or save. Errors in the content of the
+	// file are io::Errors of kind InvalidData, with a FormatError inside
+	pub fn read_from<R: Read>(reader: R) -> io::Result<Self> {
+		WaveletTrie::read_from_with_progress(reader, |_| {})
+	}
+
+	// like read_from; "progress" is called regularly while reading
+	pub fn read_from_with_progress<R: Read, F: FnMut(Progress)>(reader: R, mut progress: F) -> io::Result<Self> {
+		let mut input = HashingReader::new(reader);
+		let mut header = [0u8; HEADER_LEN];
+		input.read_exact(&mut header)?;
+		let header_info = decode_header(&header).map_err(invalid_data)?;
+		if header_info.encoding != ENCODING_NODE_LIST {
+			// not made for streaming; read it all
+			let mut rest = (&header[..]).chain(input.inner);
+			return WaveletTrie::load(&mut rest).map_err(invalid_data);
+		}
+		let mut report = Progress {
+			bytes: HEADER_LEN as u64,
+			total_bytes: HEADER_LEN as u64 + header_info.payload_len + 4,
+			nodes: 0,
+			total_nodes: header_info.node_count
+		};
+		let mut reported_bytes = 0;
+		let (nodes, root) = WaveletTrie::read_node_list(&mut input, header_info.node_count, Some(&mut |nodes, bytes| {
+			report.nodes = nodes;
+			report.bytes = HEADER_LEN as u64 + bytes;
+			if report.bytes - reported_bytes >= PROGRESS_INTERVAL {
+				progress(report);
+				reported_bytes = report.bytes;
+			}
+		})).map_err(invalid_data)?;
+
+		if input.bytes != HEADER_LEN as u64 + header_info.payload_len {
+			return Err(invalid_data(FormatError::Corrupt("the payload length does not match the header")));
+		}
+		if report.nodes != header_info.node_count {
+			return Err(invalid_data(FormatError::Corrupt("the number of nodes does not match the header")));
+		}
+		let found = input.hasher.clone().finalize();
+		let mut checksum = [0u8; 4];
+		input.inner.read_exact(&mut checksum)?;
+		let expected = bytes_to_u32(&checksum);
+		if expected != found {
+			return Err(invalid_data(FormatError::ChecksumMismatch { expected, found }));
+		}
+		let trie = WaveletTrie::from_nodes(nodes, root);
+		if trie.len() != header_info.length {
+			return Err(invalid_data(FormatError::Corrupt("the number of sequences does not match the header")));
+		}
+		report.bytes = report.total_bytes;
+		progress(report);
+		Ok(trie)
+	}
+}
+
+fn invalid_data(err: FormatError) -> io::Error {
+	match err {
+		FormatError::Io(err) => err,
+		err => io::Error::new(io::ErrorKind::InvalidData, err)
+	}
+}
+
+// a buffered writer that computes the checksum of what goes through
+struct HashingWriter<W: Write> {
+	inner: BufWriter<W>,
+	hasher: Hasher,
+	bytes: u64
+}
+
+impl<W: Write> HashingWriter<W> {
+	fn new(writer: W) -> Self {
+		HashingWriter {
+			inner: BufWriter::with_capacity(BUFFER_SIZE, writer),
+			hasher: Hasher::new(),
+			bytes: 0
+		}
+	}
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let written = self.inner.write(buf)?;
+		self.hasher.update(&buf[..written]);
+		self.bytes += written as u64;
+		Ok(written)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.inner.flush()
+	}
+}
+
+// a buffered reader that computes the checksum of what goes through
+struct HashingReader<R: Read> {
+	inner: BufReader<R>,
+	hasher: Hasher,
+	bytes: u64
+}
+
+impl<R: Read> HashingReader<R> {
+	fn new(reader: R) -> Self {
+		HashingReader {
+			inner: BufReader::with_capacity(BUFFER_SIZE, reader),
+			hasher: Hasher::new(),
+			bytes: 0
+		}
+	}
+}
+
+impl<R: Read> Read for HashingReader<R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let read = self.inner.read(buf)?;
+		self.hasher.update(&buf[..read]);
+		self.bytes += read as u64;
+		Ok(read)
+	}
+}
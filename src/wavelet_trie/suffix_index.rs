@@ -0,0 +1,209 @@
+extern crate dyn_bit_vec;
+
+use self::dyn_bit_vec::DBVec;
+use std::cmp::Ordering;
+use wavelet_trie::{WaveletTrie, DecodeError, TERMINATOR};
+
+// how strings are reversed before they go into the trie
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Reversal {
+	Bytes,    // reverse the UTF-8 bytes; suffixes are matched byte by byte
+	Chars     // reverse the chars; the reversed strings are valid UTF-8 themselves
+}
+
+/// An index that answers suffix ("ends with") queries on a sequence of strings.
+///
+/// Every string is stored reversed in a wavelet trie, so a suffix query becomes
+/// a prefix query on that trie. The strings are also kept in their normal order
+/// in a second wavelet trie, so prefix queries and combined prefix and suffix
+/// queries can be answered as well. Both tries hold every string, so the index
+/// takes about twice the memory of a single wavelet trie of the strings.
+///
+/// # Examples
+///
+/// ```rust
+/// extern crate wavelet_trie;
+///
+/// use wavelet_trie::wavelet_trie::suffix_index::{SuffixIndex, Reversal};
+///
+/// let mut index = SuffixIndex::new(Reversal::Chars);
+/// index.append("src/lib.rs").unwrap();
+/// index.append("README.md").unwrap();
+/// index.append("src/wavelet_trie/mod.rs").unwrap();
+///
+/// // all files ending in .rs
+/// assert_eq!(vec![0, 2], index.select_all_ends_with(".rs"));
+///
+/// // files in src/ ending in .rs, within positions 1 up to 3
+/// assert_eq!(1, index.count_starts_ends_with("src/", ".rs", 1, 3));
+///
+/// assert_eq!("README.md", index.access(1).unwrap());
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SuffixIndex {
+	reversal: Reversal,
+	reversed: WaveletTrie,   // every string reversed
+	forward: WaveletTrie     // every string as is
+}
+
+impl SuffixIndex {
+
+	// constructor
+	pub fn new(reversal: Reversal) -> Self {
+		SuffixIndex {
+			reversal,
+			reversed: WaveletTrie::new(),
+			forward: WaveletTrie::new()
+		}
+	}
+
+	pub fn reversal(&self) -> Reversal {
+		self.reversal
+	}
+
+	pub fn len(&self) -> u64 {
+		self.reversed.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	// appends a string to the index at last position
+	pub fn append(&mut self, text: &str) -> Result<(), &'static str> {
+		let index = self.len();
+		self.insert(text, index)
+	}
+
+	pub fn insert(&mut self, text: &str, index: u64) -> Result<(), &'static str> {
+		self.reversed.insert(&self.reversed_to_bitvec(text, true), index)?;
+		if let Err(reason) = self.forward.insert(&WaveletTrie::text_to_bitvec(text), index) {
+			// keep both tries aligned
+			self.reversed.delete(index);
+			return Err(reason);
+		}
+		Ok(())
+	}
+
+	pub fn delete(&mut self, index: u64) {
+		self.reversed.delete(index);
+		self.forward.delete(index);
+	}
+
+	// retrieves the string at the given index, reversed back to its original order
+	pub fn access(&self, index: u64) -> Result<String, DecodeError> {
+		let sequence = self.reversed.access(index);
+		if !sequence.len().is_multiple_of(8) {
+			return Err(DecodeError::MissingTerminator);
+		}
+		let mut bytes = sequence.to_bytes();
+		if bytes.pop() != Some(TERMINATOR) {
+			return Err(DecodeError::MissingTerminator);
+		}
+		match self.reversal {
+			Reversal::Bytes => {
+				bytes.reverse();
+				String::from_utf8(bytes).map_err(DecodeError::InvalidUtf8)
+			},
+			Reversal::Chars => {
+				let reversed = String::from_utf8(bytes).map_err(DecodeError::InvalidUtf8)?;
+				Ok(reversed.chars().rev().collect())
+			}
+		}
+	}
+
+	// counts the number of strings ending with "suffix" up to index - 1
+	pub fn rank_ends_with(&self, suffix: &str, index: u64) -> Option<u64> {
+		self.reversed.rank(&self.reversed_to_bitvec(suffix, false), index)
+	}
+
+	// finds the position of the occurrence_nr-th string ending with "suffix"
+	// an occurrence number starts at 1
+	pub fn select_ends_with(&self, suffix: &str, occurrence_nr: u64) -> Option<u64> {
+		self.reversed.select(&self.reversed_to_bitvec(suffix, false), occurrence_nr)
+	}
+
+	// finds the positions of all strings ending with "suffix"
+	pub fn select_all_ends_with(&self, suffix: &str) -> Vec<u64> {
+		self.reversed.select_all(&self.reversed_to_bitvec(suffix, false))
+	}
+
+	// finds the positions of all strings starting with "prefix"
+	pub fn select_all_starts_with(&self, prefix: &str) -> Vec<u64> {
+		self.forward.select_all_str_prefix(prefix)
+	}
+
+	// counts the strings ending with "suffix" on positions start up to end - 1
+	pub fn count_ends_with(&self, suffix: &str, start: u64, end: u64) -> u64 {
+		let sequence = self.reversed_to_bitvec(suffix, false);
+		Self::count_in_range(&self.reversed, &sequence, start, end)
+	}
+
+	// counts the strings starting with "prefix" on positions start up to end - 1
+	pub fn count_starts_with(&self, prefix: &str, start: u64, end: u64) -> u64 {
+		let sequence = WaveletTrie::prefix_to_bitvec(prefix);
+		Self::count_in_range(&self.forward, &sequence, start, end)
+	}
+
+	// counts the strings starting with "prefix" and ending with "suffix" on positions start up to end - 1.
+	// only the strings in the range with the prefix or the suffix are selected, not all of them
+	pub fn count_starts_ends_with(&self, prefix: &str, suffix: &str, start: u64, end: u64) -> u64 {
+		let end = end.min(self.len());
+		if start >= end {
+			return 0;
+		}
+		let with_prefix = Self::select_in_range(&self.forward, &WaveletTrie::prefix_to_bitvec(prefix), start, end);
+		if with_prefix.is_empty() {
+			return 0;
+		}
+		let with_suffix = Self::select_in_range(&self.reversed, &self.reversed_to_bitvec(suffix, false), start, end);
+
+		// both lists are sorted, so a merge finds the common positions
+		let mut count = 0;
+		let (mut i, mut j) = (0, 0);
+		while i < with_prefix.len() && j < with_suffix.len() {
+			match with_prefix[i].cmp(&with_suffix[j]) {
+				Ordering::Less => i += 1,
+				Ordering::Greater => j += 1,
+				Ordering::Equal => {
+					count += 1;
+					i += 1;
+					j += 1;
+				}
+			}
+		}
+		count
+	}
+
+	// the positions of "sequence" on positions start up to end - 1, in order: the
+	// ranks at start and end give the occurrence numbers in the range
+	fn select_in_range(trie: &WaveletTrie, sequence: &DBVec, start: u64, end: u64) -> Vec<u64> {
+		let rank_start = trie.rank(sequence, start).unwrap_or(0);
+		let rank_end = trie.rank(sequence, end).unwrap_or(0);
+		let occurrence_nrs: Vec<u64> = (rank_start + 1..rank_end + 1).collect();
+		trie.select_many(sequence, &occurrence_nrs).into_iter().flatten().collect()
+	}
+
+	fn count_in_range(trie: &WaveletTrie, sequence: &DBVec, start: u64, end: u64) -> u64 {
+		let end = end.min(trie.len());
+		if start >= end {
+			return 0;
+		}
+		let rank_end = trie.rank(sequence, end).unwrap_or(0);
+		let rank_start = trie.rank(sequence, start).unwrap_or(0);
+		rank_end - rank_start
+	}
+
+	// reverses "text" according to the reversal mode; the terminator is
+	// only added for complete strings, not for suffixes that are queried
+	fn reversed_to_bitvec(&self, text: &str, terminate: bool) -> DBVec {
+		let mut bytes: Vec<u8> = match self.reversal {
+			Reversal::Bytes => text.bytes().rev().collect(),
+			Reversal::Chars => text.chars().rev().collect::<String>().into_bytes()
+		};
+		if terminate {
+			bytes.push(TERMINATOR);
+		}
+		DBVec::from_bytes(&bytes)
+	}
+}
@@ -1,8 +1,21 @@
 #[cfg(test)]
 mod tests {
 	extern crate dyn_bit_vec;
+	extern crate crc32fast;
 	use self::dyn_bit_vec::DBVec;
-	use wavelet_trie::WaveletTrie;
+	use self::crc32fast::Hasher;
+	use wavelet_trie::{WaveletTrie, DecodeError};
+	use wavelet_trie::normalized::NormalizedWaveletTrie;
+	use wavelet_trie::suffix_index::{SuffixIndex, Reversal};
+	use wavelet_trie::composite::{CompositeWaveletTrie, CompositeKey, KeyQuery, FieldType};
+	use wavelet_trie::timestamp::{DateTime, Granularity, count_per};
+	use wavelet_trie::file_format::{FormatError, Encoding, encode_header, u32_to_bytes, u64_to_bytes};
+	use wavelet_trie::mapped::MappedWaveletTrie;
+	use wavelet_trie::positions::Positions;
+	use wavelet_trie::persistent::{PersistentWaveletTrie, PersistError};
+	use wavelet_trie::text_io::{LineOptions, LineEndings, InvalidUtf8, CsvOptions, TextError};
+	use wavelet_trie::json::JsonError;
+	use wavelet_trie::graph::{GraphOptions, GraphLabels};
 	use std::collections::HashMap;
 	use std::io;
 	use std::io::prelude::*;
@@ -15,6 +28,15 @@ mod tests {
 		assert_ranks(&wt, sequences);
 	}
 
+	// the same pseudo random numbers below max for the same seed in every run
+	fn pseudo_random(seed: u64) -> impl FnMut(u64) -> u64 {
+		let mut random = seed;
+		move |max| {
+			random = random.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+			(random >> 33) % max
+		}
+	}
+
 	fn assert_ranks(wt: &WaveletTrie, sequences: &[DBVec]) {
 		let len = wt.len();
 		let mut sequence_counter = HashMap::new();
@@ -235,7 +257,7 @@ mod tests {
 		let sequences = &[s1.copy(), s2.copy(), s3.copy(), s4.copy(), s3.copy(), s4.copy(), s3.copy(), s3.copy()];
 		println!("{:?}", wt);
 		assert_ranks(&wt, sequences);
-		wt.print_stats();
+		assert_eq!(4, wt.stats().nr_leaves);
 	}
 
 	#[test]
@@ -457,16 +479,269 @@ mod tests {
 		assert_eq!(Ok(()), wt.append_str("Dit is een test"));
 		assert_eq!(Ok(()), wt.append_str("Dit is een teletubbie"));
 		//println!("{:?}", wt);
-		assert_eq!(Some(2), wt.rank_str("Dit is", 2));
-		assert_eq!(None, wt.rank_str("st", 2));
-		assert_eq!(Some(1), wt.rank_str("Dit is een tele", 2));
+		assert_eq!(Some(2), wt.rank_str_prefix("Dit is", 2));
+		assert_eq!(None, wt.rank_str_prefix("st", 2));
+		assert_eq!(Some(1), wt.rank_str_prefix("Dit is een tele", 2));
 		assert_eq!(String::from("Dit is een test"), wt.access_str(0).unwrap());
 		assert_eq!(String::from("Dit is een teletubbie"), wt.access_str(1).unwrap());
-		assert_eq!(Some(0), wt.select_str("Dit is een test", 1));
-		assert_eq!(Some(1), wt.select_str("Dit is een teletubbie", 1));
-		assert_eq!(Some(1), wt.select_str("Dit is een te", 2));
-		assert_eq!(vec![0, 1], wt.select_all_str("Dit is een"));
-		wt.print_stats();
+		assert_eq!(Some(0), wt.select_str_exact("Dit is een test", 1));
+		assert_eq!(Some(1), wt.select_str_exact("Dit is een teletubbie", 1));
+		assert_eq!(Some(1), wt.select_str_prefix("Dit is een te", 2));
+		assert_eq!(vec![0, 1], wt.select_all_str_prefix("Dit is een"));
+		assert_eq!(2, wt.stats().nr_leaves);
+	}
+
+	#[test]
+	fn str_exact_and_prefix() {
+		let mut wt = WaveletTrie::new();
+		assert_eq!(Ok(()), wt.append_str("test"));
+		assert_eq!(Ok(()), wt.append_str("testing"));
+		assert_eq!(Ok(()), wt.append_str("test"));
+		assert_eq!(Ok(()), wt.append_str("tester"));
+
+		// exact queries only find the complete string
+		assert_eq!(Some(2), wt.rank_str_exact("test", 4));
+		assert_eq!(Some(1), wt.rank_str_exact("test", 2));
+		assert_eq!(None, wt.rank_str_exact("tes", 4));
+		assert_eq!(Some(2), wt.select_str_exact("test", 2));
+		assert_eq!(None, wt.select_str_exact("tes", 1));
+		assert_eq!(vec![0, 2], wt.select_all_str_exact("test"));
+		assert_eq!(vec![3], wt.select_all_str_exact("tester"));
+		assert!(wt.select_all_str_exact("testi").is_empty());
+
+		// prefix queries find every string starting with the text
+		assert_eq!(Some(4), wt.rank_str_prefix("test", 4));
+		assert_eq!(Some(3), wt.select_str_prefix("test", 4));
+		assert_eq!(vec![0, 1, 2, 3], wt.select_all_str_prefix("test"));
+		assert_eq!(vec![1], wt.select_all_str_prefix("testi"));
+		assert!(wt.select_all_str_prefix("toast").is_empty());
+	}
+
+	#[test]
+	fn access_str_without_terminator() {
+		// sequences not appended with append_str can not be decoded
+		let mut wt = WaveletTrie::new();
+		assert_eq!(Ok(()), wt.append(&DBVec::from_bytes("abc".as_bytes())));
+		let mut odd = DBVec::from_bytes(&[0b0]);
+		odd.push(true);
+		assert_eq!(Ok(()), wt.append(&odd));
+		assert_eq!(Ok(()), wt.append_str("abd"));
+		assert_eq!(Err(DecodeError::MissingTerminator), wt.access_str(0));
+		assert_eq!(Err(DecodeError::MissingTerminator), wt.access_str(1));
+		assert_eq!(Ok(String::from("abd")), wt.access_str(2));
+
+		let mut wt = WaveletTrie::new();
+		assert_eq!(Ok(()), wt.append(&DBVec::from_bytes(&[0xff, 0b0])));
+		match wt.access_str(0) {
+			Err(DecodeError::InvalidUtf8(_)) => {},
+			other => panic!("expected invalid UTF-8, got {:?}", other)
+		}
+	}
+
+	#[test]
+	fn normalized_str_ops() {
+		let mut wt = NormalizedWaveletTrie::keeping_originals();
+		assert_eq!(Ok(()), wt.append_str("Müller"));
+		assert_eq!(Ok(()), wt.append_str("Mu\u{308}ller"));
+		assert_eq!(Ok(()), wt.append_str("MÜLLER-Lüdenscheidt"));
+		assert_eq!(Ok(()), wt.append_str("Meyer"));
+
+		assert_eq!(vec![0, 1], wt.select_all_str_exact("müller"));
+		assert_eq!(vec![0, 1], wt.select_all_str_exact("mu\u{308}LLER"));
+		assert_eq!(vec![0, 1, 2], wt.select_all_str_prefix("müller"));
+		assert_eq!(vec![0, 1, 2], wt.select_all_str_prefix("MU\u{308}"));
+		assert!(wt.select_all_str_prefix("mu").is_empty());
+		assert_eq!(Some(2), wt.rank_str_exact("MÜLLER", 4));
+		assert_eq!(Some(3), wt.rank_str_prefix("m", 3));
+		assert_eq!(Some(2), wt.select_str_prefix("Müller", 3));
+		assert_eq!(Some(3), wt.select_str_exact("meyer", 1));
+
+		// originals are kept, keys are normalized
+		assert_eq!(Ok(String::from("Mu\u{308}ller")), wt.access_str(1));
+		assert_eq!(Ok(String::from("müller")), wt.access_key(1));
+		assert_eq!(Ok(String::from("MÜLLER-Lüdenscheidt")), wt.access_str(2));
+
+		wt.delete(0);
+		assert_eq!(3, wt.len());
+		assert_eq!(vec![0], wt.select_all_str_exact("Müller"));
+		assert_eq!(Ok(String::from("Mu\u{308}ller")), wt.access_str(0));
+
+		// full case folding, not just lowercase: ß is ss, and final and other sigmas are the same
+		let mut wt = NormalizedWaveletTrie::new();
+		assert_eq!(Ok(()), wt.append_str("Straße"));
+		assert_eq!(Ok(()), wt.append_str("ΣΟΦΟΣ"));
+		assert_eq!(vec![0], wt.select_all_str_exact("STRASSE"));
+		assert_eq!(vec![0], wt.select_all_str_prefix("strass"));
+		assert_eq!(vec![1], wt.select_all_str_exact("σοφος"));
+		assert_eq!(vec![1], wt.select_all_str_exact("σοφοσ"));
+		assert_eq!(Ok(String::from("strasse")), wt.access_key(0));
+
+		// without originals, the key is all there is
+		let mut wt = NormalizedWaveletTrie::new();
+		assert_eq!(Ok(()), wt.append_str("Müller"));
+		assert!(!wt.keeps_originals());
+		assert_eq!(Ok(String::from("müller")), wt.access_str(0));
+	}
+
+	#[test]
+	fn suffix_index() {
+		for reversal in &[Reversal::Bytes, Reversal::Chars] {
+			let mut index = SuffixIndex::new(*reversal);
+			assert_eq!(Ok(()), index.append("src/lib.rs"));
+			assert_eq!(Ok(()), index.append("gerald@ugent.be"));
+			assert_eq!(Ok(()), index.append("src/wavelet_trie/mod.rs"));
+			assert_eq!(Ok(()), index.append("Cargo.toml"));
+			assert_eq!(Ok(()), index.append("bart@ugent.be"));
+			assert_eq!(Ok(()), index.append("tests/wavelet_trie.rs"));
+			assert_eq!(Ok(()), index.append("Straße.rs"));
+			assert_eq!(7, index.len());
+
+			assert_eq!(vec![0, 2, 5, 6], index.select_all_ends_with(".rs"));
+			assert_eq!(vec![1, 4], index.select_all_ends_with("@ugent.be"));
+			assert_eq!(vec![6], index.select_all_ends_with("ße.rs"));
+			assert!(index.select_all_ends_with("@gmail.com").is_empty());
+			assert_eq!(Some(2), index.rank_ends_with(".rs", 5));
+			assert_eq!(Some(4), index.select_ends_with("@ugent.be", 2));
+			assert_eq!(None, index.select_ends_with("@ugent.be", 3));
+
+			assert_eq!(vec![0, 2], index.select_all_starts_with("src/"));
+			assert_eq!(3, index.count_ends_with(".rs", 1, 7));
+			assert_eq!(1, index.count_starts_with("src/", 1, 7));
+			assert_eq!(2, index.count_starts_ends_with("src/", ".rs", 0, 7));
+			assert_eq!(1, index.count_starts_ends_with("src/", ".rs", 1, 100));
+			assert_eq!(0, index.count_starts_ends_with("src/", ".toml", 0, 7));
+			assert_eq!(0, index.count_starts_ends_with("src/", ".rs", 5, 2));
+			assert_eq!(0, index.count_starts_ends_with("src/", ".rs", 1, 2));
+			assert_eq!(1, index.count_starts_ends_with("src/", ".rs", 2, 3));
+			assert_eq!(2, index.count_starts_ends_with("", ".rs", 2, 6));
+
+			assert_eq!(Ok(String::from("gerald@ugent.be")), index.access(1));
+			assert_eq!(Ok(String::from("Straße.rs")), index.access(6));
+
+			index.delete(0);
+			assert_eq!(vec![1, 4, 5], index.select_all_ends_with(".rs"));
+			assert_eq!(Ok(String::from("src/wavelet_trie/mod.rs")), index.access(1));
+		}
+	}
+
+	#[test]
+	fn composite_keys() {
+		let mut wt = CompositeWaveletTrie::new(&[FieldType::Str, FieldType::Str, FieldType::U64]);
+		let rows = vec!(
+			CompositeKey::new().with_str("ugent.be").with_str("/api/users").with_u64(200),
+			CompositeKey::new().with_str("ugent.be").with_str("/index.html").with_u64(404),
+			CompositeKey::new().with_str("example.org").with_str("/api/users").with_u64(200),
+			CompositeKey::new().with_str("ugent.be").with_str("/api/groups").with_u64(500),
+			CompositeKey::new().with_str("ugent").with_str(".be/api").with_u64(200),
+			CompositeKey::new().with_str("ugent.be").with_str("/api\u{0}/users").with_u64(200),
+			CompositeKey::new().with_str("ugent.be").with_str("/api/users").with_u64(200)
+		);
+		for row in &rows {
+			assert_eq!(Ok(()), wt.append(row));
+		}
+		assert_eq!(7, wt.len());
+
+		// keys that do not fit the schema are refused
+		assert!(wt.append(&CompositeKey::new().with_str("ugent.be").with_u64(200)).is_err());
+		assert!(wt.append(&CompositeKey::new().with_str("ugent.be").with_str("/").with_u64(200).with_u64(1)).is_err());
+
+		// queries on leading fields
+		assert_eq!(vec![0, 1, 3, 5, 6], wt.select_all(&KeyQuery::new().with_str("ugent.be")));
+		assert_eq!(vec![0, 3, 5, 6], wt.select_all(&KeyQuery::new().with_str("ugent.be").with_str_prefix("/api")));
+		assert_eq!(vec![0, 6], wt.select_all(&KeyQuery::new().with_str("ugent.be").with_str("/api/users")));
+		assert_eq!(vec![0, 6], wt.select_all(&KeyQuery::new().with_str("ugent.be").with_str("/api/users").with_u64(200)));
+		// field boundaries are respected: "ugent" is not a prefix match of "ugent.be"
+		assert_eq!(vec![4], wt.select_all(&KeyQuery::new().with_str("ugent")));
+		assert_eq!(vec![0, 1, 3, 4, 5, 6], wt.select_all(&KeyQuery::new().with_str_prefix("ugent")));
+		assert_eq!(7, wt.select_all(&KeyQuery::new()).len());
+		assert_eq!(Some(2), wt.rank(&KeyQuery::new().with_str("ugent.be").with_str_prefix("/api"), 5));
+		assert_eq!(Some(3), wt.select(&KeyQuery::new().with_str("ugent.be").with_str_prefix("/api/"), 2));
+
+		// queries that do not fit the schema match nothing
+		assert!(wt.select_all(&KeyQuery::new().with_u64(200)).is_empty());
+		assert!(wt.select_all(&KeyQuery::new().with_str_prefix("ugent").with_str("/api/users")).is_empty());
+		assert_eq!(None, wt.rank(&KeyQuery::new().with_str("ugent.be").with_str("/api/users").with_str_prefix("2"), 7));
+
+		// access decodes the fields again
+		for (index, row) in rows.iter().enumerate() {
+			assert_eq!(Ok(row.fields().to_vec()), wt.access(index as u64));
+		}
+
+		wt.delete(0);
+		assert_eq!(vec![5], wt.select_all(&KeyQuery::new().with_str("ugent.be").with_str("/api/users")));
+	}
+
+	#[test]
+	fn distinct_prefixes() {
+		let mut wt = WaveletTrie::new();
+		assert!(wt.distinct_prefixes(3).is_empty());
+		for text in &["aap", "noot", "mies", "aap", "nooit"] {
+			assert_eq!(Ok(()), wt.append_str(text));
+		}
+		let per_first_byte = wt.distinct_prefixes(8);
+		assert_eq!(3, per_first_byte.len());
+		let counts: HashMap<Vec<u8>, u64> = per_first_byte.iter().map(|&(ref prefix, count)| (prefix.to_bytes(), count)).collect();
+		assert_eq!(Some(&2), counts.get(&b"a"[..]));
+		assert_eq!(Some(&1), counts.get(&b"m"[..]));
+		assert_eq!(Some(&2), counts.get(&b"n"[..]));
+
+		// longer than any sequence: every distinct sequence once
+		let all = wt.distinct_prefixes(1000);
+		assert_eq!(4, all.len());
+		assert_eq!(5, all.iter().map(|&(_, count)| count).sum::<u64>());
+		assert_eq!(vec![(DBVec::new(), 5)], wt.distinct_prefixes(0));
+	}
+
+	#[test]
+	fn timestamp_keys() {
+		// unix seconds roundtrip, including leap days and dates before 1970
+		for seconds in &[0, 951782400, 1709164800, 1772323200 + 3599, -86400, -62135596800] {
+			let date_time = DateTime::from_unix_seconds(*seconds).unwrap();
+			assert_eq!(*seconds, date_time.to_unix_seconds());
+			assert_eq!(Ok(date_time), DateTime::from_key(&date_time.to_key()));
+		}
+		assert_eq!(DateTime::new(2024, 2, 29, 0, 0, 0), DateTime::from_unix_seconds(1709164800));
+		assert_eq!(None, DateTime::new(2026, 2, 29, 0, 0, 0));
+		assert_eq!(None, DateTime::new(2026, 13, 1, 0, 0, 0));
+		assert_eq!(None, DateTime::new(2026, 3, 1, 24, 0, 0));
+
+		let events = vec!(
+			DateTime::new(2026, 3, 1, 10, 15, 0).unwrap(),
+			DateTime::new(2026, 2, 28, 23, 59, 59).unwrap(),
+			DateTime::new(2026, 3, 31, 10, 0, 0).unwrap(),
+			DateTime::new(2025, 3, 1, 10, 15, 0).unwrap(),
+			DateTime::new(2026, 3, 1, 10, 45, 30).unwrap(),
+			DateTime::new(2026, 4, 1, 0, 0, 0).unwrap(),
+			DateTime::new(2026, 3, 1, 11, 0, 0).unwrap()
+		);
+		let mut wt = WaveletTrie::new();
+		for event in &events {
+			assert_eq!(Ok(()), wt.append(&event.to_key()));
+		}
+
+		// all rows in March 2026, on March 1st, and in its 10th hour
+		let march_first = DateTime::new(2026, 3, 1, 10, 0, 0).unwrap();
+		assert_eq!(vec![0, 2, 4, 6], wt.select_all(&march_first.to_key_prefix(Granularity::Month)));
+		assert_eq!(vec![0, 4, 6], wt.select_all(&march_first.to_key_prefix(Granularity::Day)));
+		assert_eq!(vec![0, 4], wt.select_all(&march_first.to_key_prefix(Granularity::Hour)));
+		assert_eq!(vec![3], wt.select_all(&DateTime::new(2025, 1, 1, 0, 0, 0).unwrap().to_key_prefix(Granularity::Year)));
+		assert_eq!(Some(2), wt.rank(&march_first.to_key_prefix(Granularity::Day), 5));
+
+		// counts per unit, in chronological order
+		let per_month = count_per(&wt, Granularity::Month).unwrap();
+		assert_eq!(vec!(
+			(DateTime::new(2025, 3, 1, 0, 0, 0).unwrap(), 1),
+			(DateTime::new(2026, 2, 1, 0, 0, 0).unwrap(), 1),
+			(DateTime::new(2026, 3, 1, 0, 0, 0).unwrap(), 4),
+			(DateTime::new(2026, 4, 1, 0, 0, 0).unwrap(), 1)
+		), per_month);
+		let per_hour = count_per(&wt, Granularity::Hour).unwrap();
+		assert_eq!(6, per_hour.len());
+		assert_eq!((DateTime::new(2026, 3, 1, 10, 0, 0).unwrap(), 2), per_hour[2]);
+		let per_second = count_per(&wt, Granularity::Second).unwrap();
+		let mut sorted_events = events.clone();
+		sorted_events.sort();
+		assert_eq!(sorted_events, per_second.iter().map(|&(date_time, _)| date_time).collect::<Vec<DateTime>>());
 	}
 
 	#[test]
@@ -671,6 +946,1188 @@ mod tests {
 		}
 	}
 
+	// a trie with strings inserted at different positions
+	#[test]
+	fn delete_merges_child() {
+		// deleting the last "Dit is" removes a leaf; its parent merges with the
+		// other child, which must bring its own positions along
+		let mut expected: Vec<&str> = vec!["Dit is een test", "Dit is", "Dit is een teletubbie", "Hello", "Dit is een test", "Hello world!", "x"];
+		let mut wt = WaveletTrie::new();
+		for text in &expected {
+			wt.append_str(text).unwrap();
+		}
+		for index in &[1, 3, 0, 3, 1] {
+			wt.delete(*index);
+			expected.remove(*index as usize);
+			assert_eq!(expected.len() as u64, wt.len());
+			for (index, text) in expected.iter().enumerate() {
+				assert_eq!(Ok(String::from(*text)), wt.access_str(index as u64));
+			}
+			let with_dit: Vec<u64> = (0..expected.len() as u64).filter(|index| expected[*index as usize].starts_with("Dit")).collect();
+			assert_eq!(with_dit, wt.select_all_str_prefix("Dit"));
+		}
+		wt.delete(0);
+		wt.delete(0);
+		assert_eq!(0, wt.len());
+		assert_eq!(Ok(()), wt.append_str("again"));
+		assert_eq!(Ok(String::from("again")), wt.access_str(0));
+	}
+
+	#[test]
+	fn failed_insert_changes_nothing() {
+		let mut wt = WaveletTrie::new();
+		wt.append_str("Hello world!").unwrap();
+		wt.append_str("x").unwrap();
+		assert!(wt.insert(&DBVec::from_bytes(b"Hel"), 0).is_err());
+		assert_eq!(2, wt.len());
+		assert_eq!(Ok(String::from("Hello world!")), wt.access_str(0));
+		assert_eq!(Ok(String::from("x")), wt.access_str(1));
+
+		// a longer sequence in a leaf with an empty prefix
+		let mut wt = WaveletTrie::new();
+		let (zero_bit, one_bit) = (DBVec::from_elem(1, false), DBVec::from_elem(1, true));
+		wt.append(&zero_bit).unwrap();
+		wt.append(&one_bit).unwrap();
+		let mut one_zero = one_bit.copy();
+		one_zero.push(false);
+		assert!(wt.append(&one_zero).is_err());
+		assert_eq!(2, wt.len());
+		assert_eq!(one_bit, wt.access(1));
+	}
+
+	fn example_str_trie() -> WaveletTrie {
+		let mut wt = WaveletTrie::new();
+		for text in &["Dit is een test", "Dit is een teletubbie", "Hello world!", "Dit is", "Hello everybody!", "Dit is een test", "x"] {
+			wt.append_str(text).unwrap();
+		}
+		wt.insert(&WaveletTrie::text_to_bitvec("Hello"), 2).unwrap();
+		wt.insert(&WaveletTrie::text_to_bitvec("Dit is een"), 0).unwrap();
+		wt
+	}
+
+	fn assert_same_strings(expected: &WaveletTrie, actual: &WaveletTrie) {
+		assert_eq!(expected.len(), actual.len());
+		for index in 0..expected.len() {
+			assert_eq!(expected.access_str(index), actual.access_str(index));
+		}
+	}
+
+	// sequences 1, 01, 001, ... make a trie as deep as there are sequences
+	const DEEP_TRIE_DEPTH: u64 = 1500;
+
+	fn deep_sequence(zeros: u64) -> DBVec {
+		let mut sequence = DBVec::from_elem(zeros, false);
+		sequence.push(true);
+		sequence
+	}
+
+	fn deep_trie() -> WaveletTrie {
+		let mut wt = WaveletTrie::new();
+		for zeros in 0..DEEP_TRIE_DEPTH {
+			wt.append(&deep_sequence(zeros)).unwrap();
+		}
+		wt
+	}
+
+	// runs a check with a small stack, which recursion through the nodes of a deep trie would overflow
+	fn with_small_stack<F: FnOnce() + Send + 'static>(check: F) {
+		let worker = ::std::thread::Builder::new().stack_size(128 * 1024).spawn(check).unwrap();
+		worker.join().unwrap();
+	}
+
+	#[test]
+	fn save_load() {
+		for encoding in &[Encoding::NodeList, Encoding::Succinct, Encoding::Flat] {
+			for wt in &[WaveletTrie::new(), example_str_trie()] {
+				let mut buffer = Vec::new();
+				assert!(wt.save_with(&mut buffer, *encoding).is_ok());
+				assert_eq!(b"WAVETRIE", &buffer[0..8]);
+				let loaded = WaveletTrie::load(&mut &buffer[..]).unwrap();
+				assert_same_strings(wt, &loaded);
+				assert_eq!(wt.select_all_str_prefix("Dit"), loaded.select_all_str_prefix("Dit"));
+			}
+
+			let binary = WaveletTrie::from_sequences(&[DBVec::from_bytes(&[0b00001000]), DBVec::from_bytes(&[0b10000000]), DBVec::from_bytes(&[0b10000100])]);
+			let mut buffer = Vec::new();
+			binary.save_with(&mut buffer, *encoding).unwrap();
+			let loaded = WaveletTrie::load(&mut &buffer[..]).unwrap();
+			for index in 0..3 {
+				assert_eq!(binary.access(index), loaded.access(index));
+			}
+		}
+	}
+
+	#[test]
+	fn save_succinct_is_smaller() {
+		// many small nodes: numbers as text
+		let mut wt = WaveletTrie::new();
+		for number in 0..2000 {
+			wt.append_str(&(number * 7919 % 1000).to_string()).unwrap();
+		}
+		let mut node_list = Vec::new();
+		wt.save_with(&mut node_list, Encoding::NodeList).unwrap();
+		let mut succinct = Vec::new();
+		wt.save_with(&mut succinct, Encoding::Succinct).unwrap();
+		let mut bincode = Vec::new();
+		wt.serialize(&mut bincode).unwrap();
+		assert!(succinct.len() * 2 < node_list.len());
+		assert!(succinct.len() * 2 < bincode.len());
+
+		let loaded = WaveletTrie::load(&mut &succinct[..]).unwrap();
+		assert_same_strings(&wt, &loaded);
+	}
+
+	#[test]
+	fn load_deeply_nested() {
+		// about a megabyte of inner nodes, each the first child of the one before, with a valid
+		// checksum: an error, not a stack overflow, also with a small stack
+		with_small_stack(|| {
+			let nr_nodes = 60_000u64;
+			let mut payload = Vec::new();
+			for _ in 0..nr_nodes {
+				payload.push(1);   // an inner node, with an empty prefix and no positions
+				payload.extend_from_slice(&[0u8; 16]);
+			}
+			let header = encode_header(0, 0, nr_nodes, payload.len() as u64);
+			let mut hasher = Hasher::new();
+			hasher.update(&header);
+			hasher.update(&payload);
+			let mut file = header.to_vec();
+			file.extend_from_slice(&payload);
+			file.extend_from_slice(&u32_to_bytes(hasher.finalize()));
+			match WaveletTrie::load(&mut &file[..]) {
+				Err(FormatError::Corrupt(_)) => {},
+				other => panic!("expected a corrupt file, got {:?}", other.map(|_| ()))
+			}
+		});
+	}
+
+	#[test]
+	fn save_load_deep_trie() {
+		with_small_stack(|| {
+			let depth = DEEP_TRIE_DEPTH;
+			let mut saved = Vec::new();
+			deep_trie().save(&mut saved).unwrap();
+			let loaded = WaveletTrie::load(&mut &saved[..]).unwrap();
+			assert_eq!(depth, loaded.len());
+			assert_eq!(2 * depth - 1, loaded.stats().nr_nodes);
+			assert_eq!(deep_sequence(depth - 1), loaded.access(depth - 1));
+		});
+	}
+
+	#[test]
+	fn load_damaged() {
+		let mut buffer = Vec::new();
+		example_str_trie().save(&mut buffer).unwrap();
+		let mut succinct = Vec::new();
+		example_str_trie().save_with(&mut succinct, Encoding::Succinct).unwrap();
+		let middle = succinct.len() / 2;
+		succinct[middle] ^= 0b1;
+		match WaveletTrie::load(&mut &succinct[..]) {
+			Err(FormatError::ChecksumMismatch { .. }) => {},
+			other => panic!("expected a checksum mismatch, got {:?}", other.map(|_| ()))
+		}
+
+		let mut flipped = buffer.clone();
+		let middle = flipped.len() / 2;
+		flipped[middle] ^= 0b100;
+		match WaveletTrie::load(&mut &flipped[..]) {
+			Err(FormatError::ChecksumMismatch { .. }) => {},
+			other => panic!("expected a checksum mismatch, got {:?}", other.map(|_| ()))
+		}
+
+		let mut bad_magic = buffer.clone();
+		bad_magic[0] = b'X';
+		match WaveletTrie::load(&mut &bad_magic[..]) {
+			Err(FormatError::BadMagic) => {},
+			other => panic!("expected bad magic, got {:?}", other.map(|_| ()))
+		}
+
+		let mut newer = buffer.clone();
+		newer[8] = 99;
+		match WaveletTrie::load(&mut &newer[..]) {
+			Err(FormatError::UnsupportedVersion(99)) => {},
+			other => panic!("expected an unsupported version, got {:?}", other.map(|_| ()))
+		}
+
+		let mut unknown_flags = buffer.clone();
+		unknown_flags[11] = 0x80;
+		match WaveletTrie::load(&mut &unknown_flags[..]) {
+			Err(FormatError::UnsupportedFlags(_)) => {},
+			other => panic!("expected unsupported flags, got {:?}", other.map(|_| ()))
+		}
+
+		let truncated = &buffer[..buffer.len() - 10];
+		match WaveletTrie::load(&mut &truncated[..]) {
+			Err(FormatError::Io(_)) => {},
+			other => panic!("expected an I/O error, got {:?}", other.map(|_| ()))
+		}
+	}
+
+	#[test]
+	fn mapped() {
+		// enough strings for several rank blocks and select samples
+		let mut wt = example_str_trie();
+		for number in 0..10000 {
+			wt.append_str(&(number * 7919 % 3000).to_string()).unwrap();
+		}
+		let path = ::std::env::temp_dir().join(format!("wavelet_trie_test_mapped_{}.wt", ::std::process::id()));
+		wt.save_with(&mut ::std::fs::File::create(&path).unwrap(), Encoding::Flat).unwrap();
+		let mapped = MappedWaveletTrie::open(&path).unwrap();
+		assert!(mapped.verify().is_ok());
+		assert_eq!(wt.len(), mapped.len());
+		for index in 0..wt.len() {
+			assert_eq!(wt.access(index), mapped.access(index));
+		}
+		for text in &["Dit", "Dit is een test", "1", "12", "299", "2999", "3000", "", "Nope"] {
+			for sequence in &[WaveletTrie::prefix_to_bitvec(text), WaveletTrie::text_to_bitvec(text)] {
+				for index in &[0, 1, 7, 513, 4097, 5000, wt.len()] {
+					assert_eq!(wt.rank(sequence, *index), mapped.rank(sequence, *index));
+				}
+				let all = wt.select_all(sequence);
+				assert_eq!(all, mapped.select_all(sequence));
+				for occurrence_nr in 1..all.len() as u64 + 2 {
+					assert_eq!(wt.select(sequence, occurrence_nr), mapped.select(sequence, occurrence_nr));
+				}
+			}
+		}
+		assert_eq!(wt.access_str(4).unwrap(), mapped.access_str(4).unwrap());
+		assert_eq!(wt.select_all_str_exact("12"), mapped.select_all_str_exact("12"));
+
+		// a damaged rank directory or select sample is found when opening, without the checksum
+		let saved = ::std::fs::read(&path).unwrap();
+		let payload_word = |word_nr: usize| {
+			let start = 40 + word_nr * 8;
+			saved[start..start + 8].iter().rev().fold(0u64, |word, &byte| word << 8 | byte as u64)
+		};
+		let (nr_nodes, prefix_bits, position_bits) = (payload_word(0), payload_word(1), payload_word(2));
+		let rank_directory = 8 + nr_nodes * 6 + prefix_bits.div_ceil(64) + position_bits.div_ceil(64);
+		let one_samples = rank_directory + position_bits.div_ceil(512) + 1;
+		let damage = |word_nr: u64, value: u64| {
+			let mut damaged = saved.clone();
+			let start = 40 + word_nr as usize * 8;
+			damaged[start..start + 8].copy_from_slice(&u64_to_bytes(value));
+			::std::fs::write(&path, &damaged).unwrap();
+			MappedWaveletTrie::open(&path)
+		};
+		for &(word_nr, value) in &[(rank_directory, 1), (rank_directory + 1, 513), (rank_directory + 2, 0), (one_samples, 1 << 40)] {
+			match damage(word_nr, value) {
+				Err(FormatError::Corrupt(_)) => {},
+				other => panic!("expected a corrupt file for word {}, got {:?}", word_nr, other.map(|_| ()))
+			}
+		}
+		// a count that is off by one but consistent with its neighbours can only be found
+		// by reading all bits; the answers are wrong then, but queries do not panic
+		if let Ok(damaged) = damage(rank_directory + 1, payload_word(rank_directory as usize + 1) + 1) {
+			for index in 0..damaged.len() {
+				damaged.access(index);
+			}
+			for text in &["Dit", "1", "12", "299"] {
+				let sequence = WaveletTrie::prefix_to_bitvec(text);
+				damaged.rank(&sequence, damaged.len());
+				damaged.select_all(&sequence);
+				damaged.select(&sequence, 1);
+			}
+		}
+
+		// only the flat encoding can be mapped
+		wt.save(&mut ::std::fs::File::create(&path).unwrap()).unwrap();
+		match MappedWaveletTrie::open(&path) {
+			Err(FormatError::UnsupportedFlags(_)) => {},
+			other => panic!("expected unsupported flags, got {:?}", other.map(|_| ()))
+		}
+		::std::fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn freeze_thaw() {
+		let empty = WaveletTrie::new().freeze();
+		assert_eq!(0, empty.len());
+		assert_eq!(None, empty.rank(&DBVec::new(), 0));
+		assert!(empty.select_all_str_prefix("a").is_empty());
+
+		let mut wt = example_str_trie();
+		for number in 0..10000 {
+			wt.append_str(&(number * 7919 % 3000).to_string()).unwrap();
+		}
+		let frozen = wt.freeze();
+		assert_eq!(wt.len(), frozen.len());
+		for index in 0..wt.len() {
+			assert_eq!(wt.access(index), frozen.access(index));
+		}
+		for text in &["Dit", "Dit is een test", "1", "12", "299", "2999", "3000", "", "Nope"] {
+			for index in &[0, 1, 7, 513, 4097, 5000, wt.len()] {
+				assert_eq!(wt.rank_str_exact(text, *index), frozen.rank_str_exact(text, *index));
+				assert_eq!(wt.rank_str_prefix(text, *index), frozen.rank_str_prefix(text, *index));
+			}
+			let all = wt.select_all_str_prefix(text);
+			assert_eq!(all, frozen.select_all_str_prefix(text));
+			assert_eq!(wt.select_all_str_exact(text), frozen.select_all_str_exact(text));
+			for occurrence_nr in 1..all.len() as u64 + 2 {
+				assert_eq!(wt.select_str_prefix(text, occurrence_nr), frozen.select_str_prefix(text, occurrence_nr));
+				assert_eq!(wt.select_str_exact(text, occurrence_nr), frozen.select_str_exact(text, occurrence_nr));
+			}
+		}
+		assert_eq!(wt.access_str(4).unwrap(), frozen.access_str(4).unwrap());
+
+		let mut thawed = frozen.thaw();
+		assert_same_strings(&wt, &thawed);
+		thawed.append_str("Dit is nieuw").unwrap();
+		assert_eq!(wt.len() + 1, thawed.len());
+		assert_eq!(thawed.select_all_str_prefix("Dit"), thawed.freeze().select_all_str_prefix("Dit"));
+	}
+
+	#[test]
+	fn freeze_after_deletes() {
+		// deleting from the end shrinks the positions, which can leave old bits after their end
+		let mut wt = WaveletTrie::new();
+		for number in 0..3000 {
+			wt.append_str(&(number % 10).to_string()).unwrap();
+		}
+		for index in (1000..3000).rev() {
+			if index % 10 >= 5 {
+				wt.delete(index);
+			}
+		}
+		let frozen = wt.freeze();
+		assert_eq!(wt.len(), frozen.len());
+		for text in &["0", "4", "5", "9"] {
+			for index in &[0, 513, 1000, 1700, wt.len()] {
+				assert_eq!(wt.rank_str_exact(text, *index), frozen.rank_str_exact(text, *index));
+			}
+			assert_eq!(wt.select_all_str_exact(text), frozen.select_all_str_exact(text));
+		}
+		assert_same_strings(&wt, &frozen.thaw());
+	}
+
+	#[test]
+	fn freeze_thaw_deep_trie() {
+		// a frozen copy answers the same, and thaws to the same trie
+		with_small_stack(|| {
+			let depth = DEEP_TRIE_DEPTH;
+			let frozen = deep_trie().freeze();
+			assert_eq!(depth, frozen.len());
+			for &zeros in &[0, 1, depth / 2, depth - 1] {
+				assert_eq!(deep_sequence(zeros), frozen.access(zeros));
+				assert_eq!(Some(1), frozen.rank(&deep_sequence(zeros), depth));
+				assert_eq!(Some(zeros), frozen.select(&deep_sequence(zeros), 1));
+			}
+			assert_eq!((100..depth).collect::<Vec<u64>>(), frozen.select_all(&DBVec::from_elem(100, false)));
+			let thawed = frozen.thaw();
+			assert_eq!(depth, thawed.len());
+			assert_eq!(2 * depth - 1, thawed.stats().nr_nodes);
+			assert_eq!(deep_sequence(depth - 1), thawed.access(depth - 1));
+		});
+	}
+
+	#[test]
+	fn run_length_positions() {
+		// runs of random length, changed at random places; compared to a Vec<bool>
+		let mut positions = Positions::new();
+		let mut expected: Vec<bool> = Vec::new();
+		let mut next_random = pseudo_random(42);
+		let mut was_run_length = false;
+		for round in 0..1000 {
+			let operation = next_random(10);
+			if operation < 1 && !expected.is_empty() {
+				let index = next_random(expected.len() as u64);
+				positions.delete(index);
+				expected.remove(index as usize);
+			} else if operation < 2 {
+				let index = next_random(expected.len() as u64 + 1);
+				let bit = next_random(2) == 1;
+				positions.insert(bit, index);
+				expected.insert(index as usize, bit);
+			} else {
+				let bit = round / 100 % 2 == 1;
+				for _ in 0..next_random(200) {
+					positions.push(bit);
+					expected.push(bit);
+				}
+			}
+			was_run_length |= positions.is_run_length();
+			assert_eq!(expected.len() as u64, positions.len());
+			if round % 100 == 0 {
+				let mut ones = 0;
+				for (index, bit) in expected.iter().enumerate() {
+					assert_eq!(*bit, positions.get(index as u64));
+					assert_eq!(ones, positions.rank(true, index as u64));
+					assert_eq!(index as u64 - ones, positions.rank(false, index as u64));
+					if *bit {
+						ones += 1;
+						assert_eq!(Some(index as u64), positions.select(true, ones));
+					} else {
+						assert_eq!(Some(index as u64), positions.select(false, index as u64 + 1 - ones));
+					}
+				}
+				assert_eq!(None, positions.select(true, ones + 1));
+				assert_eq!(None, positions.select(false, expected.len() as u64 - ones + 1));
+				assert_eq!(expected.len() as u64, positions.bits().len());
+			}
+		}
+		assert!(was_run_length);
+
+		// many random bits are stored plain
+		let mut noise = Positions::new();
+		for _ in 0..5000 {
+			noise.push(next_random(2) == 1);
+		}
+		assert!(!noise.is_run_length());
+	}
+
+	#[test]
+	fn run_length_log_levels() {
+		// long runs of the same log level
+		let mut wt = WaveletTrie::new();
+		let mut levels = Vec::new();
+		for run in 0..40 {
+			let level = ["INFO", "WARN", "ERROR", "DEBUG"][run % 4];
+			for _ in 0..500 {
+				wt.append_str(level).unwrap();
+				levels.push(level);
+			}
+		}
+		assert!(wt.root_node().positions.is_run_length());
+		assert!(wt.root_node().positions.allocated_bytes() * 8 < wt.len() as usize / 4);
+		assert_eq!((0..500).chain(2000..2500).collect::<Vec<u64>>(), wt.select_all_str_exact("INFO")[0..1000].to_vec());
+		assert_eq!(Some(5000), wt.rank_str_exact("ERROR", 20000));
+		assert_eq!("DEBUG", wt.access_str(1999).unwrap());
+
+		let built = WaveletTrie::from_sequences(&levels.iter().map(|level| WaveletTrie::text_to_bitvec(level)).collect::<Vec<DBVec>>());
+		assert!(built.root_node().positions.is_run_length());
+		assert_same_strings(&wt, &built);
+	}
+
+	fn test_directory(name: &str) -> ::std::path::PathBuf {
+		let directory = ::std::env::temp_dir().join(format!("wavelet_trie_test_{}_{}", name, ::std::process::id()));
+		let _ = ::std::fs::remove_dir_all(&directory);
+		directory
+	}
+
+	fn assert_strings(expected: &[&str], wt: &WaveletTrie) {
+		assert_eq!(expected.len() as u64, wt.len());
+		for (index, text) in expected.iter().enumerate() {
+			assert_eq!(Ok(String::from(*text)), wt.access_str(index as u64));
+		}
+	}
+
+	#[test]
+	fn persistent_replay() {
+		let directory = test_directory("persistent_replay");
+		{
+			let mut wt = PersistentWaveletTrie::open(&directory).unwrap();
+			for text in &["Dit is een test", "Hello", "Dit is", "x", "Hello world!"] {
+				wt.append_str(text).unwrap();
+			}
+			wt.delete(2).unwrap();
+			wt.insert_str("y", 0).unwrap();
+			wt.set_str(2, "Dit is nieuw").unwrap();
+
+			// rejected operations change nothing and are not logged
+			match wt.insert(&DBVec::from_bytes(b"Hel"), 0) {
+				Err(PersistError::Rejected(_)) => {},
+				other => panic!("expected a rejection, got {:?}", other)
+			}
+			match wt.set(0, &DBVec::from_bytes(b"Hel")) {
+				Err(PersistError::Rejected(_)) => {},
+				other => panic!("expected a rejection, got {:?}", other)
+			}
+			match wt.delete(10) {
+				Err(PersistError::Rejected(_)) => {},
+				other => panic!("expected a rejection, got {:?}", other)
+			}
+			wt.sync().unwrap();
+			assert_strings(&["y", "Dit is een test", "Dit is nieuw", "x", "Hello world!"], wt.trie());
+		}
+		{
+			let mut wt = PersistentWaveletTrie::open(&directory).unwrap();
+			assert_strings(&["y", "Dit is een test", "Dit is nieuw", "x", "Hello world!"], wt.trie());
+			wt.checkpoint().unwrap();
+			wt.delete(0).unwrap();
+			wt.append_str("z").unwrap();
+			wt.checkpoint().unwrap();
+			wt.set_str(0, "Dit is anders").unwrap();
+		}
+		let mut names: Vec<String> = ::std::fs::read_dir(&directory).unwrap()
+			.map(|entry| entry.unwrap().file_name().into_string().unwrap())
+			.collect();
+		names.sort();
+		assert_eq!(vec!["snapshot-2.wt", "wal-2.log"], names);
+		let wt = PersistentWaveletTrie::open(&directory).unwrap();
+		assert_strings(&["Dit is anders", "Dit is nieuw", "x", "Hello world!", "z"], wt.trie());
+		::std::fs::remove_dir_all(&directory).unwrap();
+	}
+
+	#[test]
+	fn persistent_torn_log() {
+		let directory = test_directory("persistent_torn_log");
+		{
+			let mut wt = PersistentWaveletTrie::open(&directory).unwrap();
+			for text in &["a", "b", "c"] {
+				wt.append_str(text).unwrap();
+			}
+		}
+		// a crash in the middle of writing the last record
+		let log_path = directory.join("wal-0.log");
+		let log_len = ::std::fs::metadata(&log_path).unwrap().len();
+		{
+			let log = ::std::fs::OpenOptions::new().write(true).open(&log_path).unwrap();
+			log.set_len(log_len - 3).unwrap();
+		}
+		{
+			let mut wt = PersistentWaveletTrie::open(&directory).unwrap();
+			assert_strings(&["a", "b"], wt.trie());
+			wt.append_str("d").unwrap();
+		}
+		// garbage after the last record
+		{
+			let mut log = ::std::fs::OpenOptions::new().append(true).open(&log_path).unwrap();
+			log.write_all(&[2, 0, 0, 0, 0, 0, 0, 0, 0, 1, 2, 3, 4]).unwrap();
+		}
+		{
+			let mut wt = PersistentWaveletTrie::open(&directory).unwrap();
+			assert_strings(&["a", "b", "d"], wt.trie());
+			wt.delete(0).unwrap();
+		}
+		let wt = PersistentWaveletTrie::open(&directory).unwrap();
+		assert_strings(&["b", "d"], wt.trie());
+		::std::fs::remove_dir_all(&directory).unwrap();
+	}
+
+	#[test]
+	fn persistent_damaged_record() {
+		let directory = test_directory("persistent_damaged_record");
+		{
+			let mut wt = PersistentWaveletTrie::open(&directory).unwrap();
+			for text in &["a", "b", "c"] {
+				wt.append_str(text).unwrap();
+			}
+		}
+		// a byte in the index of the first record flips
+		let log_path = directory.join("wal-0.log");
+		let mut log = ::std::fs::read(&log_path).unwrap();
+		let log_len = log.len() as u64;
+		log[24 + 1] ^= 1;
+		::std::fs::write(&log_path, &log).unwrap();
+		match PersistentWaveletTrie::open(&directory) {
+			Err(PersistError::Format(FormatError::Corrupt(_))) => {},
+			other => panic!("expected a corrupt log, got {:?}", other.map(|wt| wt.len()))
+		}
+		assert_eq!(log_len, ::std::fs::metadata(&log_path).unwrap().len());
+
+		// the records after it are still there
+		log[24 + 1] ^= 1;
+		::std::fs::write(&log_path, &log).unwrap();
+		let wt = PersistentWaveletTrie::open(&directory).unwrap();
+		assert_strings(&["a", "b", "c"], wt.trie());
+		::std::fs::remove_dir_all(&directory).unwrap();
+	}
+
+	#[test]
+	fn streaming_write_read() {
+		for wt in &[WaveletTrie::new(), example_str_trie()] {
+			let mut saved = Vec::new();
+			wt.save(&mut saved).unwrap();
+			let mut written = Vec::new();
+			let mut reports = Vec::new();
+			wt.write_to_with_progress(&mut written, |progress| reports.push(progress)).unwrap();
+			assert_eq!(saved, written);
+			let last = *reports.last().unwrap();
+			assert_eq!(written.len() as u64, last.bytes);
+			assert_eq!(last.total_nodes, last.nodes);
+
+			let read = WaveletTrie::read_from(&written[..]).unwrap();
+			assert_same_strings(wt, &read);
+		}
+
+		// long strings make files of more than a megabyte, so progress is reported on the way;
+		// with one node, it is reported right before the checksum
+		let many_texts: Vec<String> = (0..600).map(|nr| format!("{}{}", nr, "x".repeat(2000))).collect();
+		for wt in &[WaveletTrie::from_iter_str(&many_texts), WaveletTrie::from_iter_str(vec!["x".repeat(1 << 20)])] {
+			let mut written = Vec::new();
+			let mut reports = Vec::new();
+			wt.write_to_with_progress(&mut written, |progress| reports.push(progress)).unwrap();
+			assert!(reports.len() > 1);
+			assert!(reports.iter().all(|progress| progress.bytes <= progress.total_bytes));
+			assert_eq!(written.len() as u64, reports.last().unwrap().bytes);
+			let mut read_reports = Vec::new();
+			WaveletTrie::read_from_with_progress(&written[..], |progress| read_reports.push(progress)).unwrap();
+			assert!(read_reports.len() > 1);
+			assert!(read_reports.iter().all(|progress| progress.bytes <= progress.total_bytes));
+		}
+
+		// other encodings are read too
+		let mut succinct = Vec::new();
+		example_str_trie().save_with(&mut succinct, Encoding::Succinct).unwrap();
+		assert_same_strings(&example_str_trie(), &WaveletTrie::read_from(&succinct[..]).unwrap());
+
+		// damaged files are errors, not panics
+		let mut written = Vec::new();
+		example_str_trie().write_to(&mut written).unwrap();
+		let mut damaged = written.clone();
+		let last = damaged.len() - 10;
+		damaged[last] ^= 1;
+		assert_eq!(io::ErrorKind::InvalidData, WaveletTrie::read_from(&damaged[..]).unwrap_err().kind());
+		for cut in &[10, 45, written.len() - 1] {
+			assert!(WaveletTrie::read_from(&written[..*cut]).is_err());
+		}
+	}
+
+	#[test]
+	fn streaming_deep_trie() {
+		let sequences: Vec<DBVec> = (0..300).map(deep_sequence).collect();
+		let wt = WaveletTrie::from_sequences(&sequences);
+		let mut written = Vec::new();
+		wt.write_to(&mut written).unwrap();
+		let mut saved = Vec::new();
+		wt.save(&mut saved).unwrap();
+		assert_eq!(saved, written);
+		let read = WaveletTrie::read_from(&written[..]).unwrap();
+		for (index, sequence) in sequences.iter().enumerate() {
+			assert_eq!(*sequence, read.access(index as u64));
+		}
+	}
+
+	#[test]
+	fn deep_trie_without_recursion() {
+		with_small_stack(|| {
+			let depth = DEEP_TRIE_DEPTH;
+			let mut wt = deep_trie();
+			assert_eq!(depth, wt.len());
+			assert_eq!(2 * depth - 1, wt.stats().nr_nodes);
+			for &zeros in &[0, 1, depth / 2, depth - 1] {
+				assert_eq!(deep_sequence(zeros), wt.access(zeros));
+				assert_eq!(Some(1), wt.rank(&deep_sequence(zeros), depth));
+				assert_eq!(Some(zeros), wt.select(&deep_sequence(zeros), 1));
+			}
+			assert_eq!((100..depth).collect::<Vec<u64>>(), wt.select_all(&DBVec::from_elem(100, false)));
+			assert_eq!(depth as usize, wt.distinct_prefixes(depth + 1).len());
+
+			// a prefix of a sequence in the trie changes nothing
+			let mut longer = deep_sequence(depth / 2);
+			longer.push(false);
+			assert!(wt.insert(&longer, 5).is_err());
+			assert_eq!(depth, wt.len());
+			assert_eq!(deep_sequence(5), wt.access(5));
+
+			let copy = wt.clone();
+			for zeros in (depth / 2..depth).rev() {
+				wt.delete(zeros);
+			}
+			wt.delete(0);
+			assert_eq!(depth / 2 - 1, wt.len());
+			assert_eq!(deep_sequence(1), wt.access(0));
+			assert_eq!(deep_sequence(depth / 2 - 1), wt.access(depth / 2 - 2));
+			assert_eq!(deep_sequence(depth - 1), copy.access(depth - 1));
+
+			let sequences: Vec<DBVec> = (0..300).map(deep_sequence).collect();
+			let wt = WaveletTrie::from_sequences(&sequences);
+			assert_eq!(deep_sequence(299), wt.access(299));
+		});
+	}
+
+	#[test]
+	fn bulk_build() {
+		// the same trie as appending one by one, from unsorted input with repeats
+		let mut next_random = pseudo_random(7);
+		let mut texts = Vec::new();
+		for nr in 0..2000u64 {
+			let value = next_random(300);
+			texts.push(match nr % 3 {
+				0 => format!("user/{}", value),
+				1 => format!("user/{}/settings", value % 17),
+				_ => format!("{}", value * 7919)
+			});
+		}
+		texts.push(String::new());
+		let mut appended = WaveletTrie::new();
+		for text in &texts {
+			appended.append_str(text).unwrap();
+		}
+		let built = WaveletTrie::from_iter_str(&texts);
+		assert_eq!(appended.json_structure(), built.json_structure());
+		assert_same_strings(&appended, &built);
+		assert_eq!(appended.stats().nr_nodes, built.stats().nr_nodes);
+
+		// plain sequences, and the corner cases
+		let sequences: Vec<DBVec> = (0..texts.len()).map(|index| appended.access(index as u64)).collect();
+		assert_eq!(appended.json_structure(), WaveletTrie::from_sequences(&sequences).json_structure());
+		assert_eq!(0, WaveletTrie::from_iter_str(Vec::<&str>::new()).len());
+		let same = WaveletTrie::from_iter_str(vec!["x"; 5]);
+		assert_eq!(vec![0, 1, 2, 3, 4], same.select_all_str_exact("x"));
+		assert_eq!(1, same.stats().nr_nodes);
+		let empty_sequences = WaveletTrie::from_sequences(&[DBVec::new(), DBVec::new()]);
+		assert_eq!(2, empty_sequences.len());
+		assert_eq!(DBVec::new(), empty_sequences.access(1));
+	}
+
+	#[test]
+	#[should_panic(expected = "prefix free")]
+	fn bulk_build_not_prefix_free() {
+		let mut longer = DBVec::from_bytes(&[0b1010]);
+		longer.push(true);
+		WaveletTrie::from_sequences(&[DBVec::from_bytes(&[0b1010]), longer]);
+	}
+
+	#[test]
+	fn parallel_bulk_build() {
+		let texts: Vec<String> = (0..20000u64).map(|nr| format!("{}/{}", nr % 7, (nr * 7919) % 3001)).collect();
+		let sequences: Vec<DBVec> = texts.iter().map(|text| WaveletTrie::text_to_bitvec(text)).collect();
+		let sequential = WaveletTrie::from_sequences(&sequences);
+		let mut expected = Vec::new();
+		sequential.serialize(&mut expected).unwrap();
+		for &threads in &[0, 1, 2, 3, 8] {
+			let parallel = WaveletTrie::from_sequences_parallel(&sequences, threads);
+			let mut serialized = Vec::new();
+			parallel.serialize(&mut serialized).unwrap();
+			assert!(expected == serialized, "different trie with {} threads", threads);
+		}
+		let parallel = WaveletTrie::from_sequences_parallel(&sequences, 4);
+		assert_eq!(sequential.json_structure(), parallel.json_structure());
+		assert_eq!(texts[18000], parallel.access_str(18000).unwrap());
+		assert_eq!(WaveletTrie::from_sequences_parallel(&sequences[..10], 4).json_structure(), WaveletTrie::from_sequences(&sequences[..10]).json_structure());
+		assert_eq!(0, WaveletTrie::from_sequences_parallel(&[], 4).len());
+	}
+
+	#[test]
+	#[should_panic(expected = "prefix free")]
+	fn parallel_bulk_build_not_prefix_free() {
+		let mut sequences: Vec<DBVec> = (0..10000u64).map(|nr| WaveletTrie::text_to_bitvec(&nr.to_string())).collect();
+		sequences.push(DBVec::from_bytes(b"1234"));
+		WaveletTrie::from_sequences_parallel(&sequences, 4);
+	}
+
+	#[test]
+	fn append_batch() {
+		let first: Vec<&str> = vec!["blue", "green", "blue", "red"];
+		let batches: Vec<Vec<&str>> = vec![
+			vec![],
+			vec!["blue", "blue"],                        // only occurrences in leaves
+			vec!["black", "green", "brown", "blue"],     // splits, and new subtries on the way
+			vec!["", "yellow", "bla", "yellow"]          // a split at the root
+		];
+		let mut appended = WaveletTrie::new();
+		let mut batched = WaveletTrie::new();
+		for batch in vec![first].into_iter().chain(batches) {
+			for text in &batch {
+				appended.append_str(text).unwrap();
+			}
+			batched.append_batch(&batch.iter().map(|text| WaveletTrie::text_to_bitvec(text)).collect::<Vec<DBVec>>()).unwrap();
+			assert_eq!(appended.json_structure(), batched.json_structure());
+			assert_same_strings(&appended, &batched);
+		}
+
+		// a failing batch changes nothing
+		let before = batched.json_structure();
+		let mut no_terminator = DBVec::from_bytes(b"blue");
+		no_terminator.push(true);
+		assert!(batched.append_batch(&[WaveletTrie::text_to_bitvec("white"), DBVec::from_bytes(b"blue")]).is_err());
+		assert!(batched.append_batch(&[DBVec::from_bytes(b"purple"), no_terminator.copy(), DBVec::from_bytes(b"pur")]).is_err());
+		assert_eq!(before, batched.json_structure());
+
+		// extend with strings and with sequences
+		let mut extended = WaveletTrie::new();
+		extended.extend(vec!["blue", "green", "blue", "red"]);
+		extended.extend(vec!["black", "green"].into_iter().map(WaveletTrie::text_to_bitvec));
+		let mut expected = WaveletTrie::new();
+		for text in &["blue", "green", "blue", "red", "black", "green"] {
+			expected.append_str(text).unwrap();
+		}
+		assert_eq!(expected.json_structure(), extended.json_structure());
+
+		// long runs stay run-length encoded
+		let mut levels = WaveletTrie::new();
+		for run in 0..20 {
+			let level = ["INFO", "WARN", "ERROR"][run % 3];
+			levels.extend(vec![level; 500]);
+		}
+		assert_eq!(10000, levels.len());
+		assert!(levels.root_node().positions.is_run_length());
+		assert_eq!(Some(3500), levels.rank_str_exact("INFO", 10000));
+	}
+
+	#[test]
+	#[should_panic(expected = "prefix of another one")]
+	fn extend_not_prefix_free() {
+		let mut wt = WaveletTrie::new();
+		wt.extend(vec![DBVec::from_bytes(b"ab"), DBVec::from_bytes(b"a")]);
+	}
+
+	#[test]
+	fn positions_push_runs() {
+		let mut plain = Positions::new();
+		let mut runs = Positions::from_elem(3000, true);
+		let mut expected = DBVec::from_elem(3000, true);
+		plain.append(DBVec::from_elem(3000, true));
+		for round in 0..40u64 {
+			let mut bits = DBVec::new();
+			for index in 0..round * 7 {
+				bits.push(index % 5 == 0 || round % 3 == 0);
+			}
+			plain.append(bits.copy());
+			runs.append(bits.copy());
+			expected.append_vec(&mut bits);
+			plain.push_run(round % 2 == 0, round * 11);
+			runs.push_run(round % 2 == 0, round * 11);
+			expected.append_vec(&mut DBVec::from_elem(round * 11, round % 2 == 0));
+		}
+		assert_eq!(expected, *plain.bits());
+		assert_eq!(expected, *runs.bits());
+		for index in (0..expected.len()).step_by(97) {
+			assert_eq!(expected.rank(true, index), runs.rank(true, index));
+		}
+	}
+
+	#[test]
+	fn rank_select_many() {
+		let wt = example_str_trie();
+		let mut queries: Vec<DBVec> = Vec::new();
+		for index in 0..wt.len() {
+			let sequence = wt.access(index);
+			queries.push(DBVec::from_bytes(&sequence.to_bytes()[..1]));
+			queries.push(sequence);
+		}
+		let mut longer = wt.access(0);
+		longer.push(true);
+		queries.push(longer);
+		queries.push(DBVec::new());
+		queries.push(DBVec::from_bytes(b"not in the trie"));
+
+		let indices: Vec<u64> = (0..wt.len() + 1).rev().collect();
+		let occurrence_nrs: Vec<u64> = (1..wt.len() + 2).collect();
+		for query in &queries {
+			let ranks: Option<Vec<u64>> = indices.iter().map(|&index| wt.rank(query, index)).collect();
+			assert_eq!(ranks, wt.rank_many(query, &indices));
+			let count = wt.select_all(query).len() as u64;
+			let positions: Vec<Option<u64>> = occurrence_nrs.iter().map(|&nr| if nr <= count { wt.select(query, nr) } else { None }).collect();
+			assert_eq!(positions, wt.select_many(query, &occurrence_nrs));
+		}
+		assert_eq!(vec![None], wt.select_many(&queries[0], &[0]));
+		assert_eq!(Some(Vec::new()), wt.rank_many(&queries[0], &[]));
+		assert_eq!(None, WaveletTrie::new().rank_many(&DBVec::new(), &[0]));
+		assert_eq!(vec![None, None], WaveletTrie::new().select_many(&DBVec::new(), &[1, 2]));
+	}
+
+	#[test]
+	fn positions_select_sorted() {
+		let mut next_random = pseudo_random(3);
+		let mut noise = DBVec::new();
+		for _ in 0..3003 {
+			noise.push(next_random(3) == 0);
+		}
+		let mut runs = DBVec::new();
+		for run in 0..40 {
+			runs.append_vec(&mut DBVec::from_elem(next_random(200) + 1, run % 2 == 1));
+		}
+		for bits in [noise, runs] {
+			for positions in [Positions::Plain(bits.copy()), Positions::from_dbvec(bits.copy())] {
+				for &bit in &[false, true] {
+					let count = bits.rank(bit, bits.len());
+					// all occurrences, every third one, and a few (a select each)
+					for &step in &[1, 3, count / 5 + 1] {
+						let mut nrs: Vec<u64> = (0..count).step_by(step as usize).collect();
+						let expected: Vec<u64> = nrs.iter().map(|&nr| bits.select(bit, nr + 1).unwrap()).collect();
+						positions.select_sorted(bit, &mut nrs);
+						assert_eq!(expected, nrs);
+					}
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn select_all_and_count() {
+		let mut texts = Vec::new();
+		for nr in 0..3000u64 {
+			texts.push(match nr % 10 {
+				6 | 7 => format!("user/{}", nr % 13),
+				8 | 9 => format!("file/{}.rs", (nr * 7919) % 101),
+				_ => "INFO".to_string()
+			});
+		}
+		let wt = WaveletTrie::from_iter_str(&texts);
+		for query in &["", "INFO", "user/", "user/1", "user/12", "file/5", "x"] {
+			let sequence = DBVec::from_bytes(query.as_bytes());
+			let expected: Vec<u64> = (0..texts.len() as u64).filter(|&index| texts[index as usize].starts_with(query)).collect();
+			assert_eq!(expected, wt.select_all(&sequence));
+			assert_eq!(expected, wt.select_many(&sequence, &(1..expected.len() as u64 + 1).collect::<Vec<u64>>()).into_iter().map(Option::unwrap).collect::<Vec<u64>>());
+			assert_eq!(expected.len() as u64, wt.count(&sequence));
+			assert_eq!(expected.len() as u64, wt.count_str_prefix(query));
+		}
+		assert_eq!(1800, wt.count_str_exact("INFO"));
+		assert_eq!(0, wt.count_str_exact("user/"));
+		assert_eq!(0, WaveletTrie::new().count(&DBVec::new()));
+	}
+
+	#[test]
+	fn str_queries_match_sequence_queries() {
+		let texts = ["a", "ab", "abc", "b", "ab", "", "a\u{e9}", "abc", "a"];
+		let wt = WaveletTrie::from_iter_str(&texts);
+		for query in &["", "a", "ab", "abc", "abcd", "b", "a\u{e9}", "\u{e9}", "c"] {
+			let exact = WaveletTrie::text_to_bitvec(query);
+			let prefix = WaveletTrie::prefix_to_bitvec(query);
+			for index in 0..texts.len() as u64 + 1 {
+				assert_eq!(wt.rank(&exact, index), wt.rank_str_exact(query, index));
+				assert_eq!(wt.rank(&prefix, index), wt.rank_str_prefix(query, index));
+			}
+			for occurrence_nr in 1..texts.len() as u64 + 1 {
+				assert_eq!(wt.select(&exact, occurrence_nr), wt.select_str_exact(query, occurrence_nr));
+				assert_eq!(wt.select(&prefix, occurrence_nr), wt.select_str_prefix(query, occurrence_nr));
+			}
+			assert_eq!(wt.select_all(&exact), wt.select_all_str_exact(query));
+			assert_eq!(wt.select_all(&prefix), wt.select_all_str_prefix(query));
+			assert_eq!(wt.count(&exact), wt.count_str_exact(query));
+			assert_eq!(wt.count(&prefix), wt.count_str_prefix(query));
+		}
+		assert_eq!(Some(2), wt.rank_str_exact("ab", 9));
+		assert_eq!(Some(7), wt.rank_str_prefix("a", 9));
+		assert_eq!(None, wt.rank_str_exact("abcd", 9));
+	}
+
+	#[test]
+	fn trie_stats() {
+		let empty = WaveletTrie::new().stats();
+		assert_eq!((0, 0, 0, 0, 0), (empty.nr_nodes, empty.nr_leaves, empty.max_depth, empty.prefix_bits, empty.position_bits));
+		assert_eq!(0.0, empty.bits_per_element);
+
+		// the first bit of "a" and "c" is 1 and of "b" 0; "a" and "c" then differ in the second bit
+		let mut wt = WaveletTrie::new();
+		for text in &["a", "b", "c", "a", "b", "a"] {
+			wt.append_str(text).unwrap();
+		}
+		let stats = wt.stats();
+		assert_eq!(5, stats.nr_nodes);
+		assert_eq!(3, stats.nr_leaves);
+		assert_eq!(2, stats.max_depth);
+		assert_eq!(5.0 / 3.0, stats.average_depth);
+		assert_eq!(15 + 2 * 14, stats.prefix_bits);
+		assert_eq!(6 + 2 + 4 + 3 + 1, stats.position_bits);
+		assert_eq!(wt.heap_size(), stats.heap_bytes);
+		assert!(stats.heap_bytes * 8 >= (stats.prefix_bits + stats.position_bits) as usize);
+		assert_eq!((stats.heap_bytes * 8) as f64 / 6.0, stats.bits_per_element);
+
+		// deleted nodes are not counted, but their places in the arena still are
+		wt.delete(2);
+		let stats = wt.stats();
+		assert_eq!(3, stats.nr_nodes);
+		assert_eq!(2, stats.nr_leaves);
+		assert_eq!(1, stats.max_depth);
+		assert_eq!(5, wt.nodes.len());
+		assert_eq!(wt.heap_size(), stats.heap_bytes);
+	}
+
+	#[test]
+	fn shrink_to_fit_and_capacity() {
+		let texts: Vec<String> = (0..2000).map(|nr| format!("item {}", nr % 300)).collect();
+		let mut wt = WaveletTrie::with_capacity(2 * 300 - 1);
+		let capacity = wt.nodes.capacity();
+		assert!(capacity >= 599);
+		for text in &texts {
+			wt.append_str(text).unwrap();
+		}
+		assert_eq!(599, wt.stats().nr_nodes);
+		assert_eq!(capacity, wt.nodes.capacity());
+
+		// delete all but the last 50 strings, so most nodes go
+		for _ in 0..1950 {
+			wt.delete(0);
+		}
+		let before = wt.heap_size();
+		assert!(!wt.free.is_empty());
+		wt.shrink_to_fit();
+		assert!(wt.free.is_empty());
+		assert_eq!(wt.stats().nr_nodes as usize, wt.nodes.len());
+		assert!(wt.heap_size() < before);
+		let expected: Vec<&str> = texts[1950..].iter().map(|text| text.as_str()).collect();
+		assert_strings(&expected, &wt);
+		assert_eq!(vec![0], wt.select_all_str_exact("item 150"));
+
+		// the trie works as before, and shrinking again changes nothing
+		wt.append_str("item 150").unwrap();
+		wt.append_str("new").unwrap();
+		assert_eq!(vec![0, 50], wt.select_all_str_exact("item 150"));
+		wt.shrink_to_fit();
+		let heap_size = wt.heap_size();
+		wt.shrink_to_fit();
+		assert_eq!(heap_size, wt.heap_size());
+		assert_eq!(Some(51), wt.select_str_exact("new", 1));
+
+		wt.reserve(100);
+		assert!(wt.nodes.capacity() >= wt.nodes.len() + 100);
+
+		// the bulk paths use the reserved room too
+		let mut wt = WaveletTrie::with_capacity(2 * 300 - 1);
+		let capacity = wt.nodes.capacity();
+		wt.extend(texts.iter().map(|text| text.as_str()));
+		assert_eq!(599, wt.stats().nr_nodes);
+		assert_eq!(capacity, wt.nodes.capacity());
+		let expected: Vec<&str> = texts.iter().map(|text| text.as_str()).collect();
+		assert_strings(&expected, &wt);
+		wt.extend(vec!["item 7"]);
+		assert_eq!(capacity, wt.nodes.capacity());
+		assert_eq!(Some(2000), wt.select_str_exact("item 7", 8));
+	}
+
+	#[test]
+	fn arena_reuses_deleted_nodes() {
+		let mut wt = WaveletTrie::new();
+		for text in &["apple", "banana", "cherry", "apple", "date"] {
+			wt.append_str(text).unwrap();
+		}
+		let nr_places = wt.nodes.len();
+		assert!(wt.free.is_empty());
+
+		// deleting the only "cherry" merges its sibling into the parent: two places become free
+		wt.delete(2);
+		assert_eq!(nr_places, wt.nodes.len());
+		assert_eq!(2, wt.free.len());
+		assert_eq!(vec!["apple", "banana", "apple", "date"], (0..wt.len()).map(|index| wt.access_str(index).unwrap()).collect::<Vec<String>>());
+
+		// a split uses them again
+		wt.append_str("elderberry").unwrap();
+		assert_eq!(nr_places, wt.nodes.len());
+		assert!(wt.free.is_empty());
+		assert_eq!("elderberry", wt.access_str(4).unwrap());
+		assert_eq!(Some(2), wt.rank_str_exact("apple", 5));
+
+		// cloning and serializing copy the arena as it is
+		let copy = wt.clone();
+		assert_same_strings(&wt, &copy);
+		let mut buffer = Vec::new();
+		wt.serialize(&mut buffer).unwrap();
+		let deserialized = WaveletTrie::deserialize(&mut &buffer[..]).unwrap();
+		assert_eq!(wt.nodes.len(), deserialized.nodes.len());
+		assert_same_strings(&wt, &deserialized);
+		assert_eq!(vec![0, 2], deserialized.select_all_str_exact("apple"));
+	}
+
+	#[test]
+	fn lines_import_export() {
+		let text = "Dit is een test\nDit is anders\r\n\nDit is een test\nlast";
+		let wt = WaveletTrie::from_reader_lines(text.as_bytes(), &LineOptions::default()).unwrap();
+		assert_strings(&["Dit is een test", "Dit is anders", "", "Dit is een test", "last"], &wt);
+		let mut written = Vec::new();
+		wt.write_lines(&mut written, &LineOptions::default()).unwrap();
+		assert_eq!(&b"Dit is een test\nDit is anders\n\nDit is een test\nlast\n"[..], &written[..]);
+
+		let keep = LineOptions { line_endings: LineEndings::Keep, invalid_utf8: InvalidUtf8::Fail };
+		let wt = WaveletTrie::from_reader_lines(text.as_bytes(), &keep).unwrap();
+		assert_strings(&["Dit is een test\n", "Dit is anders\r\n", "\n", "Dit is een test\n", "last"], &wt);
+		let mut written = Vec::new();
+		wt.write_lines(&mut written, &keep).unwrap();
+		assert_eq!(text.as_bytes(), &written[..]);
+
+		let invalid: &[u8] = b"ok\nnot \xff ok\nok";
+		match WaveletTrie::from_reader_lines(invalid, &LineOptions::default()) {
+			Err(TextError::InvalidUtf8 { line: 2 }) => {},
+			other => panic!("expected invalid UTF-8 at line 2, got {:?}", other)
+		}
+		let skip = LineOptions { line_endings: LineEndings::Strip, invalid_utf8: InvalidUtf8::Skip };
+		assert_strings(&["ok", "ok"], &WaveletTrie::from_reader_lines(invalid, &skip).unwrap());
+		let replace = LineOptions { line_endings: LineEndings::Strip, invalid_utf8: InvalidUtf8::Replace };
+		assert_strings(&["ok", "not \u{fffd} ok", "ok"], &WaveletTrie::from_reader_lines(invalid, &replace).unwrap());
+		match WaveletTrie::from_reader_lines(&b"a\nb\0c"[..], &LineOptions::default()) {
+			Err(TextError::ContainsTerminator { line: 2 }) => {},
+			other => panic!("expected a terminator at line 2, got {:?}", other)
+		}
+
+		// sequences that are not strings
+		let binary = WaveletTrie::from_sequences(&[DBVec::from_bytes(&[0b00001000]), DBVec::from_bytes(&[0b10000000])]);
+		assert!(binary.write_lines(&mut Vec::new(), &LineOptions::default()).is_err());
+		let mut written = Vec::new();
+		binary.write_lines(&mut written, &skip).unwrap();
+		assert!(written.is_empty());
+	}
+
+	#[test]
+	fn csv_import_export() {
+		let csv = "level;message;count\r\nINFO;\"Started; all ok\";1\nERROR;\"Said \"\"no\"\"\nand stopped\";2\nINFO;plain;3\n";
+		let options = CsvOptions { delimiter: b';', has_header: true, invalid_utf8: InvalidUtf8::Fail };
+		let levels = WaveletTrie::from_csv_column(csv.as_bytes(), 0, &options).unwrap();
+		assert_strings(&["INFO", "ERROR", "INFO"], &levels);
+		assert_eq!(vec![0, 2], levels.select_all_str_exact("INFO"));
+		let messages = WaveletTrie::from_csv_column(csv.as_bytes(), 1, &options).unwrap();
+		assert_strings(&["Started; all ok", "Said \"no\"\nand stopped", "plain"], &messages);
+
+		let mut written = Vec::new();
+		messages.write_csv_column(&mut written, Some("message"), &options).unwrap();
+		assert_eq!("message\n\"Started; all ok\"\n\"Said \"\"no\"\"\nand stopped\"\nplain\n", String::from_utf8(written.clone()).unwrap());
+		let read_back = WaveletTrie::from_csv_column(&written[..], 0, &options).unwrap();
+		assert_same_strings(&messages, &read_back);
+
+		match WaveletTrie::from_csv_column(csv.as_bytes(), 3, &options) {
+			Err(TextError::MissingColumn { line: 2 }) => {},
+			other => panic!("expected a missing column at line 2, got {:?}", other)
+		}
+		match WaveletTrie::from_csv_column(&b"a\n\"open\nstill open"[..], 0, &CsvOptions::default()) {
+			Err(TextError::UnclosedQuote { line: 2 }) => {},
+			other => panic!("expected an unclosed quote at line 2, got {:?}", other)
+		}
+		// the line of a record after a multi-line field
+		match WaveletTrie::from_csv_column(&b"x,y\n\"a\nb\",1\nc\n"[..], 1, &CsvOptions::default()) {
+			Err(TextError::MissingColumn { line: 4 }) => {},
+			other => panic!("expected a missing column at line 4, got {:?}", other)
+		}
+	}
+
+	#[test]
+	fn json_structure() {
+		let wt = example_str_trie();
+		let structure = wt.json_structure();
+		assert_eq!(wt.len(), structure.length);
+		assert_eq!(0, structure.nodes[0].depth);
+		for (id, node) in structure.nodes.iter().enumerate() {
+			assert_eq!(id as u64, node.id);
+			assert_eq!(node.left.is_some(), node.positions.is_some());
+			if let (Some(left), Some(right)) = (node.left, node.right) {
+				assert_eq!(node.depth + 1, structure.nodes[left as usize].depth);
+				assert_eq!(left + 1, right);
+			}
+			if let Some(ref bytes) = node.prefix_bytes {
+				assert_eq!(node.prefix.len(), bytes.len() * 8);
+			}
+		}
+		let json = wt.to_json_structure();
+		let mut common_bytes = WaveletTrie::new();
+		common_bytes.append_str("abc").unwrap();
+		common_bytes.append_str("abd").unwrap();
+		assert!(common_bytes.to_json_structure().contains("\"prefix_bytes\":[97,98],\"prefix_text\":\"ab\""));
+		let rebuilt = WaveletTrie::from_json_structure(&json).unwrap();
+		assert_same_strings(&wt, &rebuilt);
+		assert_eq!(json, rebuilt.to_json_structure());
+
+		// the order of the nodes does not matter
+		let mut reversed = structure.clone();
+		reversed.nodes.reverse();
+		assert_same_strings(&wt, &WaveletTrie::from_json(&reversed).unwrap());
+		assert_eq!(0, WaveletTrie::from_json_structure(&WaveletTrie::new().to_json_structure()).unwrap().len());
+
+		let mut wrong_positions = structure.clone();
+		let flipped = if wrong_positions.nodes[0].positions.as_ref().unwrap().starts_with('0') { "1" } else { "0" };
+		wrong_positions.nodes[0].positions.as_mut().unwrap().replace_range(0..1, flipped);
+		match WaveletTrie::from_json(&wrong_positions) {
+			Err(JsonError::InvalidNode { id: 0, .. }) => {},
+			other => panic!("expected an invalid root, got {:?}", other)
+		}
+		let mut two_parents = structure.clone();
+		let (left, right) = (two_parents.nodes[0].left, two_parents.nodes[0].right);
+		two_parents.nodes[0].right = left;
+		assert!(WaveletTrie::from_json(&two_parents).is_err());
+		two_parents.nodes[0].right = right;
+		two_parents.nodes[0].left = None;
+		assert!(WaveletTrie::from_json(&two_parents).is_err());
+		let mut wrong_length = structure.clone();
+		wrong_length.length += 1;
+		assert!(WaveletTrie::from_json(&wrong_length).is_err());
+		let mut wrong_text = structure.clone();
+		wrong_text.nodes[0].prefix_text = Some("Dat is ".to_string());
+		assert!(WaveletTrie::from_json(&wrong_text).is_err());
+		match WaveletTrie::from_json_structure("{\"length\": 1}") {
+			Err(JsonError::Syntax(_)) => {},
+			other => panic!("expected a syntax error, got {:?}", other)
+		}
+	}
+
 	#[test]
 	fn doc_example() {
 		let sequence1 = DBVec::from_bytes(&[0b00001000]);
@@ -700,7 +2157,7 @@ mod tests {
 		wt.append_str("Hello everybody!");
 
 		// See on which positions the strings start with "Hell"
-		assert_eq!(vec![0, 1], wt.select_all_str("Hell"));
+		assert_eq!(vec![0, 1], wt.select_all_str_prefix("Hell"));
 
 		// get the string at position 1
 		assert_eq!("Hello everybody!", wt.access_str(1).unwrap());
@@ -714,6 +2171,80 @@ mod tests {
 
 		let stdout = io::stdout();
 		let mut out = stdout.lock();
-		wt.generate_graph(&mut out);
+		wt.generate_graph(&mut out).unwrap();
+	}
+
+	#[test]
+	fn generate_graph_with_options() {
+		let wt = example_str_trie();
+		let mut default = Vec::new();
+		wt.generate_graph(&mut default).unwrap();
+		let default = String::from_utf8(default).unwrap();
+		let nr_nodes = wt.json_structure().nodes.len();
+		// sequential numbers, no matter how deep the trie is
+		for node_nr in 0..nr_nodes {
+			assert!(default.contains(&format!(" \"{}\" [label = \"{}|", node_nr, node_nr)));
+		}
+		assert!(!default.contains(&format!(" \"{}\" [", nr_nodes)));
+		assert_eq!(nr_nodes - 1, default.matches(" -> ").count());
+		assert!(!default.contains("fillcolor"));
+
+		let mut options = GraphOptions {
+			labels: GraphLabels::PrefixText,
+			highlight: Some(DBVec::from_bytes("Hello w".as_bytes())),
+			scale_by_size: true
+		};
+		let mut graph = Vec::new();
+		wt.generate_graph_with(&mut graph, &options).unwrap();
+		let graph = String::from_utf8(graph).unwrap();
+		assert!(graph.contains("text: Hello world!"));
+		assert!(graph.contains("text: Dit is een te"));
+		assert!(graph.contains("width = 3.00, height = 2.00"));
+		// the root and every node down to "Hello world!"
+		let highlighted = graph.matches("fillcolor").count();
+		assert!(highlighted >= 3);
+		assert_eq!(highlighted - 1, graph.matches("color = \"red\"").count());
+		let leaf = graph.lines().find(|line| line.contains("text: Hello world!")).unwrap();
+		assert!(leaf.contains("fillcolor"));
+
+		// a query that leaves the trie half way highlights the nodes before that
+		options.highlight = Some(DBVec::from_bytes("Dit is een tx".as_bytes()));
+		let mut graph = Vec::new();
+		wt.generate_graph_with(&mut graph, &options).unwrap();
+		let graph = String::from_utf8(graph).unwrap();
+		let highlighted = graph.matches("fillcolor").count();
+		assert!(highlighted >= 2);
+		assert_eq!(highlighted - 1, graph.matches("color = \"red\"").count());
+		for text in &["text: Dit is een test", "text: Dit is een teletubbie", "text: Hello"] {
+			assert!(!graph.lines().any(|line| line.contains(text) && line.contains("fillcolor")));
+		}
+
+		options.labels = GraphLabels::PrefixBits;
+		// "n" leaves the trie in the prefix of the root
+		options.highlight = Some(DBVec::from_bytes("none".as_bytes()));
+		let mut graph = Vec::new();
+		wt.generate_graph_with(&mut graph, &options).unwrap();
+		let graph = String::from_utf8(graph).unwrap();
+		assert_eq!(0, graph.matches("fillcolor").count());
+		assert_eq!(0, graph.matches("color = \"red\"").count());
+		assert!(graph.contains("pref: 0"));
+	}
+
+	#[test]
+	fn generate_graph_deep_trie() {
+		// the graph highlights the way down to the deepest sequence
+		with_small_stack(|| {
+			let depth = DEEP_TRIE_DEPTH;
+			let options = GraphOptions {
+				labels: GraphLabels::PrefixText,
+				highlight: Some(deep_sequence(depth - 1)),
+				scale_by_size: false
+			};
+			let mut graph = Vec::new();
+			deep_trie().generate_graph_with(&mut graph, &options).unwrap();
+			let graph = String::from_utf8(graph).unwrap();
+			assert_eq!(2 * depth as usize - 2, graph.matches(" -> ").count());
+			assert_eq!(depth as usize, graph.matches("fillcolor").count());
+		});
 	}
 }
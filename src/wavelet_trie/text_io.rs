@@ -0,0 +1,280 @@
+// Building tries from text files with one string per line or from a column
+// of a CSV file, and writing them back. All strings are read first and then
+// inserted at once with from_sequences, which is much faster than appending
+// them one by one.
+
+extern crate dyn_bit_vec;
+
+use self::dyn_bit_vec::DBVec;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::io::{BufRead, Write};
+use wavelet_trie::{WaveletTrie, DecodeError, TERMINATOR};
+
+// what happens with the "\n" or "\r\n" at the end of a line
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineEndings {
+	Strip,  // not part of the strings; write_lines adds "\n" after every string
+	Keep    // part of the strings; write_lines adds nothing
+}
+
+// what happens with text that is not valid UTF-8
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InvalidUtf8 {
+	Fail,     // stop with an error
+	Skip,     // leave the string out
+	Replace   // replace the invalid bytes by U+FFFD
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LineOptions {
+	pub line_endings: LineEndings,
+	pub invalid_utf8: InvalidUtf8
+}
+
+impl Default for LineOptions {
+	fn default() -> Self {
+		LineOptions {
+			line_endings: LineEndings::Strip,
+			invalid_utf8: InvalidUtf8::Fail
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CsvOptions {
+	pub delimiter: u8,          // between the fields of a record
+	pub has_header: bool,       // the first record holds the names of the columns
+	pub invalid_utf8: InvalidUtf8
+}
+
+impl Default for CsvOptions {
+	fn default() -> Self {
+		CsvOptions {
+			delimiter: b',',
+			has_header: true,
+			invalid_utf8: InvalidUtf8::Fail
+		}
+	}
+}
+
+// reasons why text cannot be read into a trie or written from it.
+// Line numbers start at 1; for CSV files they are the line where the record starts.
+#[derive(Debug)]
+pub enum TextError {
+	Io(io::Error),                                      // reading or writing failed
+	InvalidUtf8 { line: u64 },                          // with InvalidUtf8::Fail
+	ContainsTerminator { line: u64 },                   // a string holds the terminator byte (0)
+	MissingColumn { line: u64 },                        // a CSV record has too few fields
+	UnclosedQuote { line: u64 },                        // a quoted CSV field does not end
+	Undecodable { index: u64, error: DecodeError }      // a sequence in the trie is not a string
+}
+
+impl fmt::Display for TextError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			TextError::Io(ref err) => write!(f, "I/O error: {}", err),
+			TextError::InvalidUtf8 { line } => write!(f, "Line {} is not valid UTF-8.", line),
+			TextError::ContainsTerminator { line } => write!(f, "Line {} contains a 0 byte, which is the string terminator.", line),
+			TextError::MissingColumn { line } => write!(f, "The record at line {} does not have the column.", line),
+			TextError::UnclosedQuote { line } => write!(f, "The quoted field at line {} is not closed.", line),
+			TextError::Undecodable { index, ref error } => write!(f, "The sequence at index {} is not a string: {}", index, error)
+		}
+	}
+}
+
+impl Error for TextError {}
+
+impl From<io::Error> for TextError {
+	fn from(err: io::Error) -> Self {
+		TextError::Io(err)
+	}
+}
+
+impl WaveletTrie {
+
+	// builds a trie with every line of "reader" as a string
+	pub fn from_reader_lines<R: BufRead>(mut reader: R, options: &LineOptions) -> Result<Self, TextError> {
+		let mut sequences = Vec::new();
+		let mut line = Vec::new();
+		let mut line_nr = 0;
+		loop {
+			line.clear();
+			if reader.read_until(b'\n', &mut line)? == 0 {
+				break;
+			}
+			line_nr += 1;
+			if options.line_endings == LineEndings::Strip {
+				strip_line_ending(&mut line);
+			}
+			if let Some(sequence) = bytes_to_sequence(&line, line_nr, options.invalid_utf8)? {
+				sequences.push(sequence);
+			}
+		}
+		Ok(WaveletTrie::from_sequences(&sequences))
+	}
+
+	// writes every string of the trie, in order, as a line. Strings holding
+	// line breaks do not read back as one line; write_csv_column quotes them
+	pub fn write_lines<W: Write>(&self, writer: W, options: &LineOptions) -> Result<(), TextError> {
+		let mut writer = io::BufWriter::new(writer);
+		for index in 0..self.len() {
+			if let Some(text) = self.string_at(index, options.invalid_utf8)? {
+				writer.write_all(text.as_bytes())?;
+				if options.line_endings == LineEndings::Strip {
+					writer.write_all(b"\n")?;
+				}
+			}
+		}
+		writer.flush()?;
+		Ok(())
+	}
+
+	// builds a trie with the field in "column" (starting at 0) of every record
+	// of a CSV file as a string. Fields can be quoted with '"', and then hold
+	// delimiters, line breaks and quotes (written as "")
+	pub fn from_csv_column<R: BufRead>(mut reader: R, column: usize, options: &CsvOptions) -> Result<Self, TextError> {
+		let mut sequences = Vec::new();
+		let mut fields = Vec::new();
+		let mut line_nr = 1;
+		let mut is_header = options.has_header;
+		loop {
+			let record_line = line_nr;
+			match read_csv_record(&mut reader, options.delimiter, &mut fields, &mut line_nr)? {
+				CsvRecord::End => break,
+				CsvRecord::UnclosedQuote => return Err(TextError::UnclosedQuote { line: record_line }),
+				CsvRecord::Fields => {}
+			}
+			if is_header {
+				is_header = false;
+				continue;
+			}
+			let field = match fields.get(column) {
+				Some(field) => field,
+				None => return Err(TextError::MissingColumn { line: record_line })
+			};
+			if let Some(sequence) = bytes_to_sequence(field, record_line, options.invalid_utf8)? {
+				sequences.push(sequence);
+			}
+		}
+		Ok(WaveletTrie::from_sequences(&sequences))
+	}
+
+	// writes the strings of the trie, in order, as a CSV file with one column,
+	// with "header" as first record if given. Fields are quoted when needed.
+	pub fn write_csv_column<W: Write>(&self, writer: W, header: Option<&str>, options: &CsvOptions) -> Result<(), TextError> {
+		let mut writer = io::BufWriter::new(writer);
+		if let Some(header) = header {
+			write_csv_field(&mut writer, header, options.delimiter)?;
+		}
+		for index in 0..self.len() {
+			if let Some(text) = self.string_at(index, options.invalid_utf8)? {
+				write_csv_field(&mut writer, &text, options.delimiter)?;
+			}
+		}
+		writer.flush()?;
+		Ok(())
+	}
+
+	// the string at index, following the policy for invalid UTF-8. None if it is skipped.
+	fn string_at(&self, index: u64, invalid_utf8: InvalidUtf8) -> Result<Option<String>, TextError> {
+		match (WaveletTrie::bitvec_to_text(&self.access(index)), invalid_utf8) {
+			(Ok(text), _) => Ok(Some(text)),
+			(Err(_), InvalidUtf8::Skip) => Ok(None),
+			(Err(DecodeError::InvalidUtf8(err)), InvalidUtf8::Replace) => Ok(Some(String::from_utf8_lossy(&err.into_bytes()).into_owned())),
+			(Err(error), _) => Err(TextError::Undecodable { index, error })
+		}
+	}
+}
+
+fn strip_line_ending(line: &mut Vec<u8>) {
+	if line.last() == Some(&b'\n') {
+		line.pop();
+		if line.last() == Some(&b'\r') {
+			line.pop();
+		}
+	}
+}
+
+// the text as a terminated sequence, following the policy for invalid UTF-8. None if it is skipped.
+fn bytes_to_sequence(bytes: &[u8], line_nr: u64, invalid_utf8: InvalidUtf8) -> Result<Option<DBVec>, TextError> {
+	if bytes.contains(&TERMINATOR) {
+		return Err(TextError::ContainsTerminator { line: line_nr });
+	}
+	let text = match (::std::str::from_utf8(bytes), invalid_utf8) {
+		(Ok(text), _) => text.to_string(),
+		(Err(_), InvalidUtf8::Fail) => return Err(TextError::InvalidUtf8 { line: line_nr }),
+		(Err(_), InvalidUtf8::Skip) => return Ok(None),
+		(Err(_), InvalidUtf8::Replace) => String::from_utf8_lossy(bytes).into_owned()
+	};
+	Ok(Some(WaveletTrie::text_to_bitvec(&text)))
+}
+
+enum CsvRecord {
+	Fields,
+	End,
+	UnclosedQuote
+}
+
+// reads the fields of the next record into "fields", and counts the lines it spans
+fn read_csv_record<R: BufRead>(reader: &mut R, delimiter: u8, fields: &mut Vec<Vec<u8>>, line_nr: &mut u64) -> io::Result<CsvRecord> {
+	fields.clear();
+	let mut line = Vec::new();
+	if reader.read_until(b'\n', &mut line)? == 0 {
+		return Ok(CsvRecord::End);
+	}
+	*line_nr += 1;
+	let mut field = Vec::new();
+	let mut in_quotes = false;
+	let mut index = 0;
+	loop {
+		if index == line.len() {
+			if !in_quotes {
+				break;
+			}
+			// a line break in a quoted field
+			line.clear();
+			index = 0;
+			if reader.read_until(b'\n', &mut line)? == 0 {
+				return Ok(CsvRecord::UnclosedQuote);
+			}
+			*line_nr += 1;
+			continue;
+		}
+		let byte = line[index];
+		index += 1;
+		if in_quotes {
+			if byte != b'"' {
+				field.push(byte);
+			} else if line.get(index) == Some(&b'"') {
+				field.push(b'"');
+				index += 1;
+			} else {
+				in_quotes = false;
+			}
+		} else if byte == b'"' && field.is_empty() {
+			in_quotes = true;
+		} else if byte == delimiter {
+			fields.push(::std::mem::take(&mut field));
+		} else if byte == b'\n' || (byte == b'\r' && line.get(index) == Some(&b'\n')) {
+			break;
+		} else {
+			field.push(byte);
+		}
+	}
+	fields.push(field);
+	Ok(CsvRecord::Fields)
+}
+
+fn write_csv_field(writer: &mut dyn Write, text: &str, delimiter: u8) -> io::Result<()> {
+	let needs_quotes = text.bytes().any(|byte| byte == delimiter || byte == b'"' || byte == b'\n' || byte == b'\r');
+	if needs_quotes {
+		writer.write_all(b"\"")?;
+		writer.write_all(text.replace('"', "\"\"").as_bytes())?;
+		writer.write_all(b"\"\n")
+	} else {
+		writer.write_all(text.as_bytes())?;
+		writer.write_all(b"\n")
+	}
+}
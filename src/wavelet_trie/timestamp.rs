@@ -0,0 +1,225 @@
+extern crate dyn_bit_vec;
+
+use self::dyn_bit_vec::DBVec;
+use wavelet_trie::{WaveletTrie, DecodeError};
+
+// bit layout of a timestamp key, most significant bit first:
+// year (16 bits), month (4), day (5), hour (5), minute (6), second (6).
+const YEAR_BITS: u64 = 16;
+const MONTH_BITS: u64 = 4;
+const DAY_BITS: u64 = 5;
+const HOUR_BITS: u64 = 5;
+const MINUTE_BITS: u64 = 6;
+const SECOND_BITS: u64 = 6;
+const FIELD_BITS: [u64; 6] = [YEAR_BITS, MONTH_BITS, DAY_BITS, HOUR_BITS, MINUTE_BITS, SECOND_BITS];
+
+const SECONDS_PER_DAY: i64 = 86400;
+
+// how precise a timestamp prefix is
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Granularity {
+	Year,
+	Month,
+	Day,
+	Hour,
+	Minute,
+	Second
+}
+
+impl Granularity {
+	// the number of leading bits of a timestamp key that make up a prefix of this granularity
+	pub fn bits(&self) -> u64 {
+		match *self {
+			Granularity::Year => YEAR_BITS,
+			Granularity::Month => YEAR_BITS + MONTH_BITS,
+			Granularity::Day => YEAR_BITS + MONTH_BITS + DAY_BITS,
+			Granularity::Hour => YEAR_BITS + MONTH_BITS + DAY_BITS + HOUR_BITS,
+			Granularity::Minute => YEAR_BITS + MONTH_BITS + DAY_BITS + HOUR_BITS + MINUTE_BITS,
+			Granularity::Second => YEAR_BITS + MONTH_BITS + DAY_BITS + HOUR_BITS + MINUTE_BITS + SECOND_BITS
+		}
+	}
+}
+
+/// A date and time (UTC) that can be stored in a wavelet trie as a fixed-width key.
+///
+/// The key holds the calendar fields from year down to second, most significant
+/// bit first. Since all keys have the same length they are prefix-free, and
+/// every calendar unit is a prefix: all rows of March 2026 are the rows whose
+/// key starts with the key prefix of 2026-03 at `Granularity::Month`.
+/// The keys are also ordered chronologically in the trie, so
+/// `WaveletTrie::distinct_prefixes` enumerates calendar units in order.
+///
+/// # Examples
+///
+/// ```rust
+/// extern crate wavelet_trie;
+///
+/// use wavelet_trie::wavelet_trie::WaveletTrie;
+/// use wavelet_trie::wavelet_trie::timestamp::{DateTime, Granularity, count_per};
+///
+/// let mut wt = WaveletTrie::new();
+/// for seconds in &[1772323200, 1772409600, 1775001600, 1772323260] {
+///     let date_time = DateTime::from_unix_seconds(*seconds).unwrap();
+///     wt.append(&date_time.to_key()).unwrap();
+/// }
+///
+/// // all rows in March 2026
+/// let march_2026 = DateTime::new(2026, 3, 1, 0, 0, 0).unwrap();
+/// assert_eq!(vec![0, 1, 3], wt.select_all(&march_2026.to_key_prefix(Granularity::Month)));
+///
+/// // rows per day
+/// let per_day = count_per(&wt, Granularity::Day).unwrap();
+/// assert_eq!((DateTime::new(2026, 3, 1, 0, 0, 0).unwrap(), 2), per_day[0]);
+/// assert_eq!(3, per_day.len());
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DateTime {
+	pub year: u16,
+	pub month: u8,     // 1 - 12
+	pub day: u8,       // 1 - 31
+	pub hour: u8,      // 0 - 23
+	pub minute: u8,    // 0 - 59
+	pub second: u8     // 0 - 59
+}
+
+impl DateTime {
+
+	// constructor; returns None if the fields do not form a valid date and time
+	pub fn new(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> Option<Self> {
+		if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year as i64, month as i64) as u8
+			|| hour > 23 || minute > 59 || second > 59 {
+			None
+		} else {
+			Some(DateTime {
+				year,
+				month,
+				day,
+				hour,
+				minute,
+				second
+			})
+		}
+	}
+
+	// converts seconds since 1970-01-01T00:00:00Z into a date and time.
+	// returns None if the year does not fit in the key (before 0 or after 65535)
+	pub fn from_unix_seconds(seconds: i64) -> Option<Self> {
+		let days = seconds.div_euclid(SECONDS_PER_DAY);
+		let second_of_day = seconds.rem_euclid(SECONDS_PER_DAY);
+		let (year, month, day) = civil_from_days(days);
+		if year < 0 || year > u16::MAX as i64 {
+			return None;
+		}
+		DateTime::new(year as u16, month as u8, day as u8,
+			(second_of_day / 3600) as u8, (second_of_day % 3600 / 60) as u8, (second_of_day % 60) as u8)
+	}
+
+	// converts the date and time into seconds since 1970-01-01T00:00:00Z
+	pub fn to_unix_seconds(&self) -> i64 {
+		days_from_civil(self.year as i64, self.month as i64, self.day as i64) * SECONDS_PER_DAY
+			+ self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64
+	}
+
+	// the complete key of this date and time, to store in a trie
+	pub fn to_key(&self) -> DBVec {
+		self.to_key_prefix(Granularity::Second)
+	}
+
+	// the first part of the key, up to the given granularity.
+	// use this as prefix in queries, e.g. "all rows in this month"
+	pub fn to_key_prefix(&self, granularity: Granularity) -> DBVec {
+		let mut prefix = DBVec::new();
+		let values = self.values();
+		for (value, nr_bits) in values.iter().zip(FIELD_BITS.iter()) {
+			if prefix.len() == granularity.bits() {
+				break;
+			}
+			push_bits(&mut prefix, *value, *nr_bits);
+		}
+		prefix
+	}
+
+	// decodes a complete key
+	pub fn from_key(key: &DBVec) -> Result<Self, DecodeError> {
+		Self::from_key_prefix(key, Granularity::Second)
+	}
+
+	// decodes a key prefix of the given granularity; the fields below the
+	// granularity get their lowest value (first month, first day, 0 hours, ...)
+	pub fn from_key_prefix(prefix: &DBVec, granularity: Granularity) -> Result<Self, DecodeError> {
+		if prefix.len() != granularity.bits() {
+			return Err(DecodeError::InvalidLayout);
+		}
+		let mut values = [0u64, 1, 1, 0, 0, 0];
+		let mut bit_nr = 0;
+		for (value, nr_bits) in values.iter_mut().zip(FIELD_BITS.iter()) {
+			if bit_nr == prefix.len() {
+				break;
+			}
+			*value = read_bits(prefix, bit_nr, *nr_bits);
+			bit_nr += *nr_bits;
+		}
+		DateTime::new(values[0] as u16, values[1] as u8, values[2] as u8, values[3] as u8, values[4] as u8, values[5] as u8)
+			.ok_or(DecodeError::InvalidLayout)
+	}
+
+	fn values(&self) -> [u64; 6] {
+		[self.year as u64, self.month as u64, self.day as u64, self.hour as u64, self.minute as u64, self.second as u64]
+	}
+}
+
+// counts the timestamps in the trie per calendar unit of the given granularity,
+// in chronological order. Every unit is represented by its first moment.
+pub fn count_per(trie: &WaveletTrie, granularity: Granularity) -> Result<Vec<(DateTime, u64)>, DecodeError> {
+	trie.distinct_prefixes(granularity.bits()).iter()
+		.map(|&(ref prefix, count)| DateTime::from_key_prefix(prefix, granularity).map(|date_time| (date_time, count)))
+		.collect()
+}
+
+// pushes the nr_bits lowest bits of value, most significant bit first
+fn push_bits(key: &mut DBVec, value: u64, nr_bits: u64) {
+	for shift in (0..nr_bits).rev() {
+		key.push((value >> shift) & 1 == 1);
+	}
+}
+
+fn read_bits(key: &DBVec, start: u64, nr_bits: u64) -> u64 {
+	(start..start + nr_bits).fold(0, |value, bit_nr| (value << 1) | key.get(bit_nr) as u64)
+}
+
+fn is_leap_year(year: i64) -> bool {
+	(year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+	match month {
+		2 => if is_leap_year(year) { 29 } else { 28 },
+		4 | 6 | 9 | 11 => 30,
+		_ => 31
+	}
+}
+
+// days since 1970-01-01 of the given date in the proleptic Gregorian calendar.
+// after H. Hinnant, "chrono-Compatible Low-Level Date Algorithms"
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+	let year = if month <= 2 { year - 1 } else { year };
+	let era = year.div_euclid(400);
+	let year_of_era = year - era * 400;
+	let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+	let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+	era * 146097 + day_of_era - 719468
+}
+
+// the inverse of days_from_civil
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+	let days = days + 719468;
+	let era = days.div_euclid(146097);
+	let day_of_era = days - era * 146097;
+	let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+	let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+	let shifted_month = (5 * day_of_year + 2) / 153;
+	let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+	let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+	let year = year_of_era + era * 400;
+	(if month <= 2 { year + 1 } else { year }, month, day)
+}